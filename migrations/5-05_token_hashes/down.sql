@@ -0,0 +1,4 @@
+-- The secrets of hashed tokens can't be recovered, so all sessions are dropped.
+DELETE FROM "tokens";
+
+ALTER TABLE "tokens" DROP COLUMN "hash";
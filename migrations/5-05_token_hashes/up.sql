@@ -0,0 +1,9 @@
+ALTER TABLE "tokens" ADD COLUMN "hash" TEXT UNIQUE;
+
+-- Tokens issued before this migration used their id as the secret. Keep them
+-- valid by storing the digest of the id and giving them a fresh, non-secret id.
+UPDATE "tokens" SET
+    "hash" = encode(sha256(convert_to("id"::TEXT, 'UTF8')), 'hex'),
+    "id" = uuid_generate_v4();
+
+ALTER TABLE "tokens" ALTER COLUMN "hash" SET NOT NULL;
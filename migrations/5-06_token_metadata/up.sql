@@ -0,0 +1,6 @@
+ALTER TABLE "tokens"
+    ADD COLUMN "user_agent" TEXT,
+    ADD COLUMN "ip_address" TEXT,
+    ADD COLUMN "last_used_at" TIMESTAMP NOT NULL DEFAULT current_timestamp;
+
+UPDATE "tokens" SET "last_used_at" = COALESCE("updated_at", "created_at");
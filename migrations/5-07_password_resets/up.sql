@@ -0,0 +1,7 @@
+CREATE TABLE "password_resets" (
+    "id" UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    "user" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "hash" TEXT UNIQUE NOT NULL,
+    "expiration" TIMESTAMP NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
@@ -0,0 +1,20 @@
+ALTER TABLE "users"
+    ADD COLUMN "totp_secret" TEXT,
+    ADD COLUMN "totp_enabled" BOOLEAN NOT NULL DEFAULT FALSE,
+    ADD COLUMN "totp_last_step" BIGINT;
+
+CREATE TABLE "recovery_codes" (
+    "id" SERIAL PRIMARY KEY,
+    "user" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "hash" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    UNIQUE ("user", "hash")
+);
+
+CREATE TABLE "login_challenges" (
+    "id" UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    "user" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "hash" TEXT UNIQUE NOT NULL,
+    "expiration" TIMESTAMP NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
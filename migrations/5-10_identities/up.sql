@@ -0,0 +1,17 @@
+CREATE TABLE "identities" (
+    "id" SERIAL PRIMARY KEY,
+    "user" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "provider" TEXT NOT NULL,
+    "subject" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    UNIQUE ("provider", "subject")
+);
+
+CREATE TABLE "oidc_states" (
+    "hash" TEXT PRIMARY KEY,
+    "provider" TEXT NOT NULL,
+    "nonce" TEXT NOT NULL,
+    "code_verifier" TEXT NOT NULL,
+    "expiration" TIMESTAMP NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
@@ -0,0 +1,10 @@
+CREATE TABLE "api_keys" (
+    "id" UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    "user" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "name" VARCHAR(64) NOT NULL,
+    "hash" TEXT UNIQUE NOT NULL,
+    "scopes" TEXT[] NOT NULL,
+    "expiration" TIMESTAMP,
+    "last_used_at" TIMESTAMP,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
@@ -0,0 +1,4 @@
+ALTER TABLE "users"
+    ADD COLUMN "email" VARCHAR(254),
+    ADD COLUMN "email_verified" BOOLEAN NOT NULL DEFAULT FALSE;
+CREATE UNIQUE INDEX "users_email_key" ON "users" (lower("email"));
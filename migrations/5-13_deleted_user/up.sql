@@ -0,0 +1,4 @@
+-- The placeholder author of content whose author deleted their account. Its
+-- name isn't a valid username and its password isn't a valid hash, so nobody
+-- can register or log in as it.
+INSERT INTO "users" ("username", "password") VALUES ('[deleted]', '!');
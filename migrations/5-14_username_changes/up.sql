@@ -0,0 +1,9 @@
+CREATE TABLE "username_changes" (
+    "id" SERIAL PRIMARY KEY,
+    "user" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "old_username" VARCHAR(20) NOT NULL,
+    "new_username" VARCHAR(20) NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX "username_changes_old_username_idx" ON "username_changes" ("old_username");
@@ -0,0 +1,7 @@
+ALTER TABLE "users"
+    DROP COLUMN "avatar_url",
+    DROP COLUMN "pronouns",
+    DROP COLUMN "location",
+    DROP COLUMN "links",
+    DROP COLUMN "website",
+    DROP COLUMN "display_name";
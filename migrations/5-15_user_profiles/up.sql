@@ -0,0 +1,7 @@
+ALTER TABLE "users"
+    ADD COLUMN "display_name" VARCHAR(50),
+    ADD COLUMN "website" VARCHAR(200),
+    ADD COLUMN "links" TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN "location" VARCHAR(100),
+    ADD COLUMN "pronouns" VARCHAR(30),
+    ADD COLUMN "avatar_url" VARCHAR(200);
@@ -0,0 +1,13 @@
+CREATE TABLE "media" (
+    "id" UUID PRIMARY KEY,
+    "user" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "content_type" TEXT NOT NULL,
+    "extension" TEXT NOT NULL,
+    "size" INT NOT NULL,
+    "width" INT NOT NULL,
+    "height" INT NOT NULL,
+    "thumbnails" INT[] NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX "media_user_idx" ON "media" ("user");
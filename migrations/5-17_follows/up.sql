@@ -0,0 +1,9 @@
+CREATE TABLE "follows" (
+    "follower" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "followee" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY ("follower", "followee"),
+    CHECK ("follower" <> "followee")
+);
+
+CREATE INDEX "follows_followee_idx" ON "follows" ("followee");
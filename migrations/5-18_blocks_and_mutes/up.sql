@@ -0,0 +1,17 @@
+CREATE TABLE "blocks" (
+    "blocker" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "blocked" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY ("blocker", "blocked"),
+    CHECK ("blocker" <> "blocked")
+);
+
+CREATE INDEX "blocks_blocked_idx" ON "blocks" ("blocked");
+
+CREATE TABLE "mutes" (
+    "muter" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "muted" UUID NOT NULL REFERENCES "users" ON DELETE CASCADE,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY ("muter", "muted"),
+    CHECK ("muter" <> "muted")
+);
@@ -0,0 +1,4 @@
+DROP INDEX "posts_status_published_at_idx";
+ALTER TABLE "posts"
+    DROP COLUMN "published_at",
+    DROP COLUMN "status";
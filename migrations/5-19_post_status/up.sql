@@ -0,0 +1,8 @@
+ALTER TABLE "posts"
+    ADD COLUMN "status" VARCHAR(16) NOT NULL DEFAULT 'published'
+        CHECK ("status" IN ('draft', 'scheduled', 'published')),
+    ADD COLUMN "published_at" TIMESTAMP;
+
+UPDATE "posts" SET "published_at" = "created_at";
+
+CREATE INDEX "posts_status_published_at_idx" ON "posts" ("status", "published_at");
@@ -0,0 +1,30 @@
+CREATE TABLE "post_revisions" (
+    "id" SERIAL PRIMARY KEY,
+    "post" INT NOT NULL REFERENCES "posts" ON DELETE CASCADE,
+    "editor" UUID REFERENCES "users" ON DELETE SET NULL,
+    "title" VARCHAR(100) NOT NULL,
+    "subtitle" VARCHAR(140) NOT NULL,
+    "body" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX "post_revisions_post_idx" ON "post_revisions" ("post");
+
+CREATE TABLE "comment_revisions" (
+    "id" SERIAL PRIMARY KEY,
+    "comment" INT NOT NULL REFERENCES "comments" ON DELETE CASCADE,
+    "editor" UUID REFERENCES "users" ON DELETE SET NULL,
+    "message" TEXT NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX "comment_revisions_comment_idx" ON "comment_revisions" ("comment");
+
+-- Existing content becomes the first revision, as it's unknown who wrote
+-- earlier versions.
+INSERT INTO "post_revisions" ("post", "title", "subtitle", "body", "created_at")
+    SELECT "id", "title", "subtitle", "body", coalesce("updated_at", "created_at")
+    FROM "posts";
+INSERT INTO "comment_revisions" ("comment", "message", "created_at")
+    SELECT "id", "message", coalesce("updated_at", "created_at")
+    FROM "comments";
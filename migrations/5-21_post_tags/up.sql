@@ -0,0 +1,7 @@
+CREATE TABLE "post_tags" (
+    "post" INT NOT NULL REFERENCES "posts" ON DELETE CASCADE,
+    "tag" VARCHAR(30) NOT NULL,
+    PRIMARY KEY ("post", "tag")
+);
+
+CREATE INDEX "post_tags_tag_idx" ON "post_tags" ("tag");
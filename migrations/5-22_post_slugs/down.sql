@@ -0,0 +1,2 @@
+DROP TABLE "old_post_slugs";
+ALTER TABLE "posts" DROP COLUMN "slug";
@@ -0,0 +1,42 @@
+ALTER TABLE "posts" ADD COLUMN "slug" VARCHAR(100);
+
+-- Existing posts get an ASCII-only slug of at most 80 characters, numbered
+-- like new ones where it would collide.
+DO $$
+DECLARE
+    post RECORD;
+    base TEXT;
+    candidate TEXT;
+    n INT;
+BEGIN
+    FOR post IN SELECT "id", "author", "title" FROM "posts" ORDER BY "id" LOOP
+        base := left(
+            trim(BOTH '-' FROM lower(regexp_replace(post."title", '[^a-zA-Z0-9]+', '-', 'g'))),
+            80
+        );
+        base := coalesce(nullif(rtrim(base, '-'), ''), 'post');
+        candidate := base;
+        n := 1;
+        WHILE EXISTS (
+            SELECT 1 FROM "posts" WHERE "author" = post."author" AND "slug" = candidate
+        ) LOOP
+            n := n + 1;
+            candidate := base || '-' || n;
+        END LOOP;
+        UPDATE "posts" SET "slug" = candidate WHERE "id" = post."id";
+    END LOOP;
+END
+$$;
+
+ALTER TABLE "posts" ALTER COLUMN "slug" SET NOT NULL;
+CREATE UNIQUE INDEX "posts_author_slug_idx" ON "posts" ("author", "slug");
+
+-- Slugs posts had before their title changed, so old links keep working.
+CREATE TABLE "old_post_slugs" (
+    "post" INT NOT NULL REFERENCES "posts" ON DELETE CASCADE,
+    "slug" VARCHAR(100) NOT NULL,
+    "created_at" TIMESTAMP NOT NULL DEFAULT current_timestamp,
+    PRIMARY KEY ("post", "slug")
+);
+
+CREATE INDEX "old_post_slugs_slug_idx" ON "old_post_slugs" ("slug");
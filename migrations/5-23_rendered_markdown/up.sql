@@ -0,0 +1,4 @@
+-- Filled in when content is written. Rows where it's NULL, like the ones
+-- already there, are rendered at startup.
+ALTER TABLE "posts" ADD COLUMN "body_html" TEXT;
+ALTER TABLE "comments" ADD COLUMN "message_html" TEXT;
@@ -0,0 +1,2 @@
+DROP INDEX "comments_search_idx";
+DROP INDEX "posts_search_idx";
@@ -0,0 +1,12 @@
+-- Expression indexes rather than stored vectors keep the tables free of
+-- columns Diesel has no type for. Search queries have to repeat the exact
+-- expressions for the indexes to be used.
+CREATE INDEX "posts_search_idx" ON "posts" USING GIN ((
+    setweight(to_tsvector('english', "title"), 'A')
+        || setweight(to_tsvector('english', "subtitle"), 'B')
+        || setweight(to_tsvector('english', "body"), 'C')
+));
+
+CREATE INDEX "comments_search_idx" ON "comments" USING GIN ((
+    to_tsvector('english', "message")
+));
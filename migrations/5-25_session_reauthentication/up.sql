@@ -0,0 +1,3 @@
+-- When the user last logged in through a linked identity provider again
+-- during the session, which confirms actions like deleting their account.
+ALTER TABLE "tokens" ADD COLUMN "reauthenticated_at" TIMESTAMP;
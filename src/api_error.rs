@@ -1,14 +1,21 @@
 use std::collections::HashMap;
 
-use actix_web::{http::StatusCode, HttpResponse, ResponseError};
-use diesel::result::Error as DieselError;
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse, ResponseError,
+};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use validator::ValidationErrors;
 use serde_json::to_string;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct ApiError {
     pub status_code: u16,
     pub message: String,
+    /// How long the client should wait before retrying, sent as the
+    /// `Retry-After` header.
+    pub retry_after: Option<Duration>,
 }
 
 impl ApiError {
@@ -16,6 +23,16 @@ impl ApiError {
         Self {
             status_code,
             message,
+            retry_after: None,
+        }
+    }
+
+    /// Creates a `429 Too Many Requests` error telling the client to retry
+    /// after `retry_after`.
+    pub fn too_many_requests(retry_after: Duration) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+            ..Self::new(429, "Too many requests, try again later.".into())
         }
     }
 }
@@ -30,6 +47,14 @@ impl From<DieselError> for ApiError {
     fn from(e: DieselError) -> Self {
         match e {
             DieselError::NotFound => ApiError::new(404, "Record not found".to_string()),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+                let message = match info.constraint_name() {
+                    Some("users_username_key") => "Username already in use.",
+                    Some("users_email_key") => "Email address already in use.",
+                    _ => "Record already exists.",
+                };
+                ApiError::new(409, message.to_string())
+            }
             e => ApiError::new(500, format!("Diesel error: {}", e)),
         }
     }
@@ -62,6 +87,12 @@ impl ResponseError for ApiError {
             "Internal Server Error".to_string()
         };
 
-        HttpResponse::build(status_code).body(message)
+        let mut response = HttpResponse::build(status_code);
+        if let Some(retry_after) = self.retry_after {
+            // Round up so clients never retry too early.
+            let seconds = retry_after.as_secs() + (retry_after.subsec_nanos() > 0) as u64;
+            response.insert_header((header::RETRY_AFTER, seconds));
+        }
+        response.body(message)
     }
 }
@@ -0,0 +1,170 @@
+use crate::{config, ApiError, ApiKey, ClientInfo, Scope, Token, User};
+use actix_web::{dev::Payload, http::header, FromRequest, HttpRequest};
+use lazy_static::lazy_static;
+use std::{
+    future::{ready, Ready},
+    net::{IpAddr, SocketAddr},
+};
+use uuid::Uuid;
+
+lazy_static! {
+    /// The reverse proxies whose `X-Forwarded-For` headers are trusted, from
+    /// the comma-separated `TRUSTED_PROXIES` environment variable.
+    static ref TRUSTED_PROXIES: Vec<IpAddr> = config::var_or("TRUSTED_PROXIES", String::new())
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .map(|proxy| {
+            proxy.parse().unwrap_or_else(|_| {
+                panic!("TRUSTED_PROXIES must list IP addresses, got {:?}", proxy)
+            })
+        })
+        .collect();
+}
+
+/// What a `User` authenticated with.
+#[derive(Debug)]
+pub enum Credential {
+    /// A session token, which grants every `Scope`.
+    Session(Token),
+    /// An API key, which only grants its own scopes.
+    ApiKey(ApiKey),
+}
+
+impl Credential {
+    /// The id of the user the credential belongs to.
+    pub fn user(&self) -> Uuid {
+        match self {
+            Self::Session(token) => token.user,
+            Self::ApiKey(key) => key.user,
+        }
+    }
+
+    /// Whether the credential grants `scope`.
+    pub fn allows(&self, scope: Scope) -> bool {
+        match self {
+            Self::Session(_) => true,
+            Self::ApiKey(key) => key.allows(scope),
+        }
+    }
+}
+
+/// The `User` making a request, authenticated by the session token or API key
+/// in the request's `Authorization: Bearer <token>` header.
+#[derive(Debug)]
+pub struct AuthenticatedUser {
+    /// The authenticated user.
+    pub user: User,
+    /// What the user authenticated with.
+    pub credential: Credential,
+}
+
+impl AuthenticatedUser {
+    /// Extracts the bearer token from the `Authorization` header.
+    fn bearer_token(req: &HttpRequest) -> Result<String, ApiError> {
+        let header = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .ok_or_else(|| ApiError::new(401, "Missing authorization header.".into()))?;
+
+        header
+            .to_str()
+            .ok()
+            .and_then(|s| s.strip_prefix("Bearer "))
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .ok_or_else(|| ApiError::new(401, "Invalid authorization header.".into()))
+    }
+
+    /// Returns an `ApiError` unless the credential grants `scope`.
+    pub fn require(&self, scope: Scope) -> Result<(), ApiError> {
+        if self.credential.allows(scope) {
+            Ok(())
+        } else {
+            Err(ApiError::new(403, format!("This API key lacks the {:?} scope.", scope.as_str())))
+        }
+    }
+
+    /// Returns the user reading content, as long as the credential grants
+    /// `Scope::Read`, so API keys without it can't see drafts and the like.
+    pub fn viewer(&self) -> Option<&User> {
+        self.credential.allows(Scope::Read).then_some(&self.user)
+    }
+
+    /// Returns the session token the user authenticated with, or an
+    /// `ApiError` if they used an API key, as managing the account requires
+    /// logging in.
+    pub fn session(&self) -> Result<&Token, ApiError> {
+        match &self.credential {
+            Credential::Session(token) => Ok(token),
+            Credential::ApiKey(_) => Err(ApiError::new(
+                403, "API keys can't be used to manage the account.".into(),
+            )),
+        }
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Self::bearer_token(req).and_then(|token| {
+            let (user, credential) = User::from_token(&token)?;
+            Ok(AuthenticatedUser { user, credential })
+        }))
+    }
+}
+
+impl FromRequest for ClientInfo {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(String::from);
+        let ip_address = client_ip(req).map(|ip| ip.to_string());
+
+        ready(Ok(ClientInfo {
+            user_agent,
+            ip_address,
+        }))
+    }
+}
+
+/// Returns the address of the client making the request. That's the peer
+/// address, unless the peer is a trusted proxy: then the `X-Forwarded-For`
+/// entries are followed from the right, as each was appended by the hop
+/// before, until one isn't a trusted proxy. Entries further left can be
+/// made up by the client.
+fn client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let mut ip = req.peer_addr()?.ip();
+    if !TRUSTED_PROXIES.contains(&ip) {
+        return Some(ip);
+    }
+
+    let forwarded: Vec<&str> = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    for entry in forwarded.into_iter().rev() {
+        match entry
+            .parse::<IpAddr>()
+            .or_else(|_| entry.parse::<SocketAddr>().map(|addr| addr.ip()))
+        {
+            Ok(hop) => ip = hop,
+            Err(_) => break,
+        }
+        if !TRUSTED_PROXIES.contains(&ip) {
+            break;
+        }
+    }
+    Some(ip)
+}
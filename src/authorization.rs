@@ -0,0 +1,211 @@
+use crate::{ApiError, Comment, Media, Post, User};
+use diesel::{
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+    AsExpression, FromSqlRow,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// The role of a `User`, determining what they are allowed to do besides
+/// managing their own content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// A regular user.
+    User,
+    /// A user that can edit and delete any post or comment.
+    Moderator,
+    /// A moderator that can also change the roles of other users.
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Moderator => "moderator",
+            Self::Admin => "admin",
+        }
+    }
+
+    /// Whether the role allows moderating other users' content.
+    pub fn is_moderator(&self) -> bool {
+        matches!(self, Self::Moderator | Self::Admin)
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql<Text, Pg> for Role {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for Role {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "user" => Ok(Self::User),
+            "moderator" => Ok(Self::Moderator),
+            "admin" => Ok(Self::Admin),
+            other => Err(format!("Unknown role {:?}", other).into()),
+        }
+    }
+}
+
+/// Something a `User` can do to a `Resource`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Edit,
+    Delete,
+    ChangeRole,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Edit => write!(f, "edit"),
+            Self::Delete => write!(f, "delete"),
+            Self::ChangeRole => write!(f, "change the role of"),
+        }
+    }
+}
+
+/// Something access to which is restricted.
+pub trait Resource {
+    /// How the resource is called in error messages.
+    const NAME: &'static str;
+
+    /// Whether `user` may perform `action` on the resource.
+    ///
+    /// ```
+    /// use ephemeris::{Action, Comment, Resource, Role, User};
+    ///
+    /// let user = |username: &str, role| User {
+    ///     id: uuid::Uuid::new_v4(),
+    ///     username: username.into(),
+    ///     password: String::new(),
+    ///     about: None,
+    ///     created_at: chrono::Utc::now().naive_utc(),
+    ///     updated_at: None,
+    ///     role,
+    ///     totp_secret: None,
+    ///     totp_enabled: false,
+    ///     totp_last_step: None,
+    ///     email: None,
+    ///     email_verified: false,
+    ///     display_name: None,
+    ///     website: None,
+    ///     links: Vec::new(),
+    ///     location: None,
+    ///     pronouns: None,
+    ///     avatar_url: None,
+    /// };
+    /// let author = user("author", Role::User);
+    /// let other = user("other", Role::User);
+    /// let moderator = user("moderator", Role::Moderator);
+    /// let admin = user("admin", Role::Admin);
+    /// let comment = Comment {
+    ///     id: 1,
+    ///     author: author.username.clone(),
+    ///     post: 1,
+    ///     message: "Hello".into(),
+    ///     created_at: chrono::Utc::now().naive_utc(),
+    ///     updated_at: None,
+    ///     message_html: None,
+    /// };
+    ///
+    /// // Authors manage their own content, which other users can't.
+    /// assert!(comment.permits(&author, Action::Edit));
+    /// assert!(comment.permits(&author, Action::Delete));
+    /// assert!(!comment.permits(&other, Action::Edit));
+    /// assert!(!comment.permits(&other, Action::Delete));
+    ///
+    /// // Moderators and admins can edit and delete anyone's content.
+    /// for user in [&moderator, &admin] {
+    ///     assert!(comment.permits(user, Action::Edit));
+    ///     assert!(comment.permits(user, Action::Delete));
+    /// }
+    ///
+    /// // Only admins change roles, but not their own.
+    /// assert!(author.permits(&admin, Action::ChangeRole));
+    /// assert!(moderator.permits(&admin, Action::ChangeRole));
+    /// assert!(!author.permits(&moderator, Action::ChangeRole));
+    /// assert!(!admin.permits(&admin, Action::ChangeRole));
+    /// assert!(!comment.permits(&admin, Action::ChangeRole));
+    /// ```
+    fn permits(&self, user: &User, action: Action) -> bool;
+}
+
+impl Resource for Post {
+    const NAME: &'static str = "post";
+
+    fn permits(&self, user: &User, action: Action) -> bool {
+        match action {
+            Action::Edit | Action::Delete => {
+                user.username == self.author || user.role.is_moderator()
+            }
+            Action::ChangeRole => false,
+        }
+    }
+}
+
+impl Resource for Comment {
+    const NAME: &'static str = "comment";
+
+    fn permits(&self, user: &User, action: Action) -> bool {
+        match action {
+            Action::Edit | Action::Delete => {
+                user.username == self.author || user.role.is_moderator()
+            }
+            Action::ChangeRole => false,
+        }
+    }
+}
+
+impl Resource for Media {
+    const NAME: &'static str = "media";
+
+    fn permits(&self, user: &User, action: Action) -> bool {
+        match action {
+            Action::Delete => user.id == self.user || user.role.is_moderator(),
+            Action::Edit | Action::ChangeRole => false,
+        }
+    }
+}
+
+impl Resource for User {
+    const NAME: &'static str = "user";
+
+    fn permits(&self, user: &User, action: Action) -> bool {
+        match action {
+            Action::Edit | Action::Delete => user.id == self.id,
+            Action::ChangeRole => user.role == Role::Admin && user.id != self.id,
+        }
+    }
+}
+
+impl User {
+    /// Whether the `User` may perform `action` on `resource`.
+    pub fn can<R: Resource>(&self, action: Action, resource: &R) -> bool {
+        resource.permits(self, action)
+    }
+
+    /// Returns an `ApiError` unless the `User` may perform `action` on
+    /// `resource`.
+    pub fn authorize<R: Resource>(&self, action: Action, resource: &R) -> Result<(), ApiError> {
+        if self.can(action, resource) {
+            Ok(())
+        } else {
+            Err(ApiError::new(403, format!("You can't {} this {}.", action, R::NAME)))
+        }
+    }
+}
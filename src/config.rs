@@ -0,0 +1,14 @@
+use std::{env, str::FromStr};
+
+/// Reads the environment variable `name` and parses it, falling back to
+/// `default` if it isn't set.
+///
+/// Panics if the variable is set but can't be parsed.
+pub fn var_or<T: FromStr>(name: &str, default: T) -> T {
+    match env::var(name) {
+        Ok(s) => s
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a valid value, got {:?}", name, s)),
+        Err(_) => default,
+    }
+}
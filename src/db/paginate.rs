@@ -4,9 +4,20 @@ use diesel::pg::Pg;
 use diesel::prelude::*;
 use diesel::query_builder::*;
 use diesel::sql_types::BigInt;
+use serde::Deserialize;
+use validator::Validate;
 
 const DEFAULT_LIMIT: i64 = 10;
 
+/// The page of a list to return.
+#[derive(Debug, Deserialize, Validate)]
+pub struct Pagination {
+    #[validate(range(min = 0))]
+    pub offset: Option<i64>,
+    #[validate(range(min = 0))]
+    pub limit: Option<i64>,
+}
+
 pub trait Paginate: Sized {
     fn paginate(self, offset: i64) -> Paginated<Self>;
 }
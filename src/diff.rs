@@ -0,0 +1,62 @@
+//! Line-based diffs between texts.
+
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::borrow::Cow;
+
+/// How a line changed between two texts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A line of a diff.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffLine {
+    pub change: Change,
+    pub line: String,
+}
+
+/// Computes the line diff turning `old` into `new`. A missing newline at the
+/// end of either text doesn't count as a change.
+///
+/// ```
+/// use ephemeris::diff::{lines, Change, DiffLine};
+///
+/// let line = |change, line: &str| DiffLine { change, line: line.into() };
+/// assert_eq!(
+///     lines("a\nb\nc", "a\nc\nd"),
+///     [
+///         line(Change::Equal, "a"),
+///         line(Change::Delete, "b"),
+///         line(Change::Equal, "c"),
+///         line(Change::Insert, "d"),
+///     ],
+/// );
+/// assert!(lines("", "").is_empty());
+/// ```
+pub fn lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let (old, new) = (terminated(old), terminated(new));
+    TextDiff::from_lines(old.as_ref(), new.as_ref())
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            change: match change.tag() {
+                ChangeTag::Equal => Change::Equal,
+                ChangeTag::Insert => Change::Insert,
+                ChangeTag::Delete => Change::Delete,
+            },
+            line: change.value().trim_end_matches(['\n', '\r']).to_owned(),
+        })
+        .collect()
+}
+
+fn terminated(text: &str) -> Cow<'_, str> {
+    if text.is_empty() || text.ends_with('\n') {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(format!("{}\n", text))
+    }
+}
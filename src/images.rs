@@ -0,0 +1,125 @@
+//! Processing of uploaded images.
+
+use crate::ApiError;
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageFormat,
+    ImageReader, Limits,
+};
+use std::io::Cursor;
+
+/// The sizes thumbnails are generated in, as the length of their longer
+/// side in pixels.
+pub const THUMBNAIL_SIZES: [u32; 3] = [64, 256, 1024];
+
+/// The largest width and height an uploaded image can have.
+const MAX_DIMENSION: u32 = 10_000;
+
+/// An uploaded image re-encoded without any metadata, together with its
+/// thumbnails.
+#[derive(Debug)]
+pub struct ProcessedImage {
+    pub content_type: &'static str,
+    pub extension: &'static str,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    /// The thumbnails by their size. Images are never scaled up, so sizes
+    /// larger than the image are left out.
+    pub thumbnails: Vec<(u32, Vec<u8>)>,
+}
+
+/// Validates an uploaded image and prepares it for storage.
+///
+/// The format is detected from the data itself rather than trusting the
+/// client. JPEG, PNG, GIF and WebP images are accepted; JPEGs stay JPEGs
+/// while everything else is stored as PNG. As the image is decoded and
+/// encoded again, metadata like EXIF (which may contain the location a photo
+/// was taken at) is dropped after applying the orientation it specifies.
+///
+/// ```
+/// use ephemeris::images::process;
+/// use image::{ImageFormat, RgbImage};
+/// use std::io::Cursor;
+///
+/// let mut png = Vec::new();
+/// RgbImage::new(400, 200)
+///     .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+///     .unwrap();
+///
+/// let image = process(&png).unwrap();
+/// assert_eq!(image.content_type, "image/png");
+/// assert_eq!((image.width, image.height), (400, 200));
+/// let sizes: Vec<_> = image.thumbnails.iter().map(|(size, _)| *size).collect();
+/// assert_eq!(sizes, [64, 256]);
+///
+/// assert!(process(b"GIF89a, but not really").is_err());
+/// assert!(process(b"<svg></svg>").is_err());
+/// ```
+pub fn process(data: &[u8]) -> Result<ProcessedImage, ApiError> {
+    let invalid =
+        || ApiError::new(415, "Only JPEG, PNG, GIF and WebP images are supported.".into());
+
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|_| invalid())?;
+    let format = reader.format().ok_or_else(invalid)?;
+    if !matches!(
+        format,
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::WebP
+    ) {
+        return Err(invalid());
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    reader.limits(limits);
+
+    let mut decoder = reader.into_decoder().map_err(|_| invalid())?;
+    let orientation = decoder.orientation().map_err(|_| invalid())?;
+    let mut image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| ApiError::new(422, format!("Couldn't decode image: {}", e)))?;
+    image.apply_orientation(orientation);
+
+    let (content_type, extension, output) = match format {
+        ImageFormat::Jpeg => ("image/jpeg", "jpg", ImageFormat::Jpeg),
+        _ => ("image/png", "png", ImageFormat::Png),
+    };
+
+    let thumbnails = THUMBNAIL_SIZES
+        .into_iter()
+        .filter(|size| *size < image.width().max(image.height()))
+        .map(|size| {
+            let thumbnail = image.resize(size, size, FilterType::Lanczos3);
+            Ok((size, encode(&thumbnail, output)?))
+        })
+        .collect::<Result<_, ApiError>>()?;
+
+    Ok(ProcessedImage {
+        content_type,
+        extension,
+        width: image.width(),
+        height: image.height(),
+        data: encode(&image, output)?,
+        thumbnails,
+    })
+}
+
+/// Encodes `image` without any metadata.
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, ApiError> {
+    let error = |e: image::ImageError| ApiError::new(500, format!("Couldn't encode image: {}", e));
+    let mut data = Vec::new();
+
+    if format == ImageFormat::Jpeg {
+        image
+            .to_rgb8()
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut data, 85))
+            .map_err(error)?;
+    } else {
+        image
+            .write_to(&mut Cursor::new(&mut data), format)
+            .map_err(error)?;
+    }
+
+    Ok(data)
+}
@@ -1,12 +1,27 @@
 #[macro_use]
 extern crate log;
 
+pub mod config;
 pub mod db;
+pub mod diff;
+pub mod images;
+pub mod mail;
+pub mod markdown;
+pub mod oidc;
+pub mod password;
+pub mod rate_limit;
 pub mod routes;
 pub mod schema;
+pub mod storage;
+pub mod tasks;
+pub mod totp;
 
 mod api_error;
+mod auth;
+mod authorization;
 mod models;
 
 pub use api_error::ApiError;
+pub use auth::{AuthenticatedUser, Credential};
+pub use authorization::{Action, Resource, Role};
 pub use models::*;
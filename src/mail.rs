@@ -0,0 +1,150 @@
+use crate::{config, ApiError};
+use chrono::Utc;
+use lazy_static::lazy_static;
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, SmtpTransport},
+    Message, Transport,
+};
+use std::{fs, path::PathBuf};
+use uuid::Uuid;
+
+lazy_static! {
+    /// The `Mailer` used to send mail, selected by the `MAILER` environment
+    /// variable.
+    static ref MAILER: Box<dyn Mailer> = match config::var_or("MAILER", "log".to_string())
+        .as_str()
+    {
+        "log" => Box::new(LogMailer),
+        "file" => Box::new(FileMailer::new(config::var_or(
+            "MAIL_DIR",
+            PathBuf::from("mail"),
+        ))),
+        "smtp" => Box::new(SmtpMailer::from_env()),
+        other => panic!("Unknown mailer {:?}", other),
+    };
+    /// The sender of all mail.
+    static ref MAIL_FROM: String =
+        config::var_or("MAIL_FROM", "ephemeris <noreply@localhost>".to_string());
+}
+
+/// A mail to be sent to a user.
+#[derive(Debug)]
+pub struct Mail {
+    /// The email address of the recipient.
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Mail {
+    /// Builds the RFC 5322 message for the mail.
+    fn message(&self) -> Result<Message, ApiError> {
+        let from: Mailbox = MAIL_FROM
+            .parse()
+            .map_err(|e| ApiError::new(500, format!("Invalid MAIL_FROM: {}", e)))?;
+        let to: Mailbox = self
+            .to
+            .parse()
+            .map_err(|e| ApiError::new(500, format!("Invalid recipient: {}", e)))?;
+
+        Message::builder()
+            .from(from)
+            .to(to)
+            .subject(&self.subject)
+            .body(self.body.to_owned())
+            .map_err(|e| ApiError::new(500, format!("Couldn't build mail: {}", e)))
+    }
+}
+
+/// Something that can deliver `Mail`.
+pub trait Mailer: Send + Sync {
+    /// Sends `mail`.
+    fn send(&self, mail: &Mail) -> Result<(), ApiError>;
+}
+
+/// A `Mailer` that only logs mail, for use during development.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: &Mail) -> Result<(), ApiError> {
+        info!("Mail to {:?}: {:?}\n{}", mail.to, mail.subject, mail.body);
+        Ok(())
+    }
+}
+
+/// A `Mailer` that writes every mail as an `.eml` file into a directory, for
+/// use during development and in tests.
+pub struct FileMailer {
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> Result<(), ApiError> {
+        let error = |e: std::io::Error| ApiError::new(500, format!("Couldn't write mail: {}", e));
+        fs::create_dir_all(&self.dir).map_err(error)?;
+
+        let path = self.dir.join(format!(
+            "{}-{}.eml",
+            Utc::now().format("%Y%m%dT%H%M%S"),
+            Uuid::new_v4(),
+        ));
+        fs::write(&path, mail.message()?.formatted()).map_err(error)?;
+
+        info!("Wrote mail to {:?} to {:?}", mail.to, path);
+        Ok(())
+    }
+}
+
+/// A `Mailer` delivering mail through an SMTP relay, configured by the
+/// `SMTP_*` environment variables.
+pub struct SmtpMailer {
+    transport: SmtpTransport,
+}
+
+impl SmtpMailer {
+    /// Creates a `SmtpMailer` from the environment.
+    ///
+    /// Panics if `SMTP_HOST` isn't set.
+    pub fn from_env() -> Self {
+        let host: String = config::var_or("SMTP_HOST", String::new());
+        if host.is_empty() {
+            panic!("SMTP_HOST must be set when using the smtp mailer");
+        }
+
+        let mut builder = SmtpTransport::relay(&host)
+            .unwrap_or_else(|e| panic!("Invalid SMTP_HOST: {}", e))
+            .port(config::var_or("SMTP_PORT", 465));
+        let username: String = config::var_or("SMTP_USERNAME", String::new());
+        if !username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                username,
+                config::var_or("SMTP_PASSWORD", String::new()),
+            ));
+        }
+
+        Self {
+            transport: builder.build(),
+        }
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> Result<(), ApiError> {
+        self.transport
+            .send(&mail.message()?)
+            .map_err(|e| ApiError::new(500, format!("Couldn't send mail: {}", e)))?;
+        Ok(())
+    }
+}
+
+/// Sends `mail` using the configured `Mailer`.
+pub fn send(mail: Mail) -> Result<(), ApiError> {
+    MAILER.send(&mail)
+}
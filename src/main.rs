@@ -1,6 +1,6 @@
 use actix_cors::Cors;
 use actix_web::{http::header, App, HttpServer};
-use ephemeris::{db, routes::init_routes};
+use ephemeris::{config, db, routes::init_routes, tasks, SearchResult, User};
 use log::info;
 use std::env;
 
@@ -10,13 +10,39 @@ async fn main() -> std::io::Result<()> {
     env_logger::init();
 
     db::init();
+    SearchResult::create_indexes()
+        .unwrap_or_else(|e| panic!("Couldn't create search indexes: {}", e));
+    User::create_initial_admin()
+        .unwrap_or_else(|e| panic!("Couldn't create initial admin: {}", e));
+    tasks::spawn();
 
-    let server = HttpServer::new(|| {
-        let cors = Cors::default()
-            .allow_any_origin()
+    // Credentials are only needed for the OIDC state cookie, so they're only
+    // allowed for known origins.
+    let origins: String = config::var_or("CORS_ORIGINS", String::new());
+    let origins: Vec<String> = origins
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(str::to_owned)
+        .collect();
+
+    let server = HttpServer::new(move || {
+        let mut cors = Cors::default()
             .allowed_methods(vec!["GET", "POST", "DELETE", "PUT"])
-            .allowed_headers(vec![header::ACCEPT, header::CONTENT_TYPE])
+            .allowed_headers(vec![
+                header::ACCEPT,
+                header::AUTHORIZATION,
+                header::CONTENT_TYPE,
+            ])
             .max_age(3600);
+        if origins.is_empty() {
+            cors = cors.allow_any_origin();
+        } else {
+            for origin in &origins {
+                cors = cors.allowed_origin(origin);
+            }
+            cors = cors.supports_credentials();
+        }
 
         App::new().wrap(cors).configure(init_routes)
     });
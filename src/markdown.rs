@@ -0,0 +1,67 @@
+//! Rendering of Markdown posts and comments to HTML that's safe to embed.
+
+use ammonia::Builder;
+use lazy_static::lazy_static;
+use pulldown_cmark::{html, Options, Parser};
+use std::borrow::Cow;
+
+/// The prefix of footnote ids, so they can't clash with the ids of the page
+/// the HTML is embedded in.
+const FOOTNOTE_PREFIX: &str = "fn-";
+
+lazy_static! {
+    static ref SANITIZER: Builder<'static> = {
+        let mut builder = Builder::default();
+        builder
+            .add_tag_attributes("code", ["class"])
+            .add_tag_attributes("sup", ["class"])
+            .add_tag_attributes("div", ["class", "id"])
+            .attribute_filter(filter_attribute);
+        builder
+    };
+}
+
+/// Only keeps the classes highlighters and footnotes need, and namespaces
+/// footnote ids and the links to them.
+fn filter_attribute<'a>(element: &str, attribute: &str, value: &'a str) -> Option<Cow<'a, str>> {
+    match (element, attribute) {
+        ("code", "class") => value
+            .strip_prefix("language-")
+            .filter(|lang| lang.chars().all(|c| c.is_ascii_alphanumeric() || "+-#_.".contains(c)))
+            .map(|_| value.into()),
+        ("sup", "class") | ("div", "class") => {
+            value.starts_with("footnote-").then_some(value.into())
+        }
+        ("div", "id") => Some(format!("{}{}", FOOTNOTE_PREFIX, value).into()),
+        ("a", "href") => match value.strip_prefix('#') {
+            Some(id) => Some(format!("#{}{}", FOOTNOTE_PREFIX, id).into()),
+            None => Some(value.into()),
+        },
+        _ => Some(value.into()),
+    }
+}
+
+/// Renders CommonMark with tables and footnotes to sanitized HTML. Fenced
+/// code blocks keep a `language-*` class for syntax highlighting.
+///
+/// ```
+/// use ephemeris::markdown::render;
+///
+/// assert_eq!(render("Hello *world*"), "<p>Hello <em>world</em></p>\n");
+/// assert_eq!(
+///     render("```rust\nfn main() {}\n```"),
+///     "<pre><code class=\"language-rust\">fn main() {}\n</code></pre>\n",
+/// );
+/// assert!(render("| a |\n|---|\n| b |").contains("<td>b</td>"));
+/// assert!(render("Note[^1]\n\n[^1]: Text").contains("href=\"#fn-1\""));
+///
+/// let html = render("<script>alert(1)</script><a href=\"javascript:x\" onclick=\"x\">a</a>");
+/// assert!(!html.contains("script") && !html.contains("onclick"));
+/// ```
+pub fn render(markdown: &str) -> String {
+    let parser = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_FOOTNOTES);
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+
+    SANITIZER.clean(&unsafe_html).to_string()
+}
@@ -0,0 +1,170 @@
+use crate::{
+    db::{self, Pagination},
+    models::slugs::MAX_SLUG_LENGTH,
+    schema::{comments, posts, users},
+    ApiError, ApiKey, Comment, CommentRevision, Identity, Media, Post, PostRevision, Role, Token,
+    User, UsernameChange,
+};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{dsl::sql, prelude::*, sql_types::Text};
+use serde::{Deserialize, Serialize};
+
+/// What happens to the posts and comments of a deleted account.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeletedContent {
+    /// Delete them together with the account.
+    #[default]
+    Delete,
+    /// Keep them, attributed to the `User::DELETED` placeholder.
+    Anonymize,
+}
+
+/// A request to delete the authenticated user's account. It has to be
+/// confirmed with the password or a second factor code, or by logging in
+/// through a linked identity provider again shortly before, as accounts
+/// created through one don't have a password.
+#[derive(Debug, Deserialize)]
+pub struct AccountDeletion {
+    pub password: Option<String>,
+    /// A TOTP or recovery code.
+    pub code: Option<String>,
+    #[serde(default)]
+    pub content: DeletedContent,
+}
+
+/// Everything stored about a user, for them to download.
+#[derive(Serialize)]
+pub struct UserExport {
+    pub username: String,
+    pub email: Option<String>,
+    #[serde(rename = "emailVerified")]
+    pub email_verified: bool,
+    pub about: Option<String>,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    pub website: Option<String>,
+    pub links: Vec<String>,
+    pub location: Option<String>,
+    pub pronouns: Option<String>,
+    #[serde(rename = "avatarUrl")]
+    pub avatar_url: Option<String>,
+    pub role: Role,
+    #[serde(rename = "twoFactorEnabled")]
+    pub two_factor_enabled: bool,
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+    #[serde(rename = "usernameChanges")]
+    pub username_changes: Vec<UsernameChange>,
+    pub posts: Vec<Post>,
+    pub comments: Vec<Comment>,
+    #[serde(rename = "postRevisions")]
+    pub post_revisions: Vec<PostRevision>,
+    #[serde(rename = "commentRevisions")]
+    pub comment_revisions: Vec<CommentRevision>,
+    /// The usernames of the users they follow.
+    pub following: Vec<String>,
+    /// The usernames of the users following them.
+    pub followers: Vec<String>,
+    /// The usernames of the users they blocked.
+    pub blocked: Vec<String>,
+    /// The usernames of the users they muted.
+    pub muted: Vec<String>,
+    pub sessions: Vec<Token>,
+    pub identities: Vec<Identity>,
+    #[serde(rename = "apiKeys")]
+    pub api_keys: Vec<ApiKey>,
+    pub media: Vec<Media>,
+    #[serde(rename = "exportedAt")]
+    pub exported_at: NaiveDateTime,
+}
+
+impl User {
+    /// The username of the placeholder anonymized content is attributed to.
+    pub const DELETED: &'static str = "[deleted]";
+
+    /// Collects everything stored about the `User`.
+    pub fn export(&self) -> Result<UserExport, ApiError> {
+        let comments = comments::table
+            .filter(comments::author.eq(&self.username))
+            .order(comments::created_at)
+            .load(&mut db::connection()?)?;
+        let all = || Pagination { offset: None, limit: None };
+        let usernames = |users: Vec<User>| users.into_iter().map(|user| user.username).collect();
+
+        Ok(UserExport {
+            username: self.username.to_owned(),
+            email: self.email.to_owned(),
+            email_verified: self.email_verified,
+            about: self.about.to_owned(),
+            display_name: self.display_name.to_owned(),
+            website: self.website.to_owned(),
+            links: self.links.to_owned(),
+            location: self.location.to_owned(),
+            pronouns: self.pronouns.to_owned(),
+            avatar_url: self.avatar_url.to_owned(),
+            role: self.role,
+            two_factor_enabled: self.totp_enabled,
+            created_at: self.created_at,
+            username_changes: self.username_changes()?,
+            posts: self.posts()?,
+            comments,
+            post_revisions: self.post_revisions()?,
+            comment_revisions: self.comment_revisions()?,
+            following: usernames(self.following(all())?),
+            followers: usernames(self.followers(all())?),
+            blocked: usernames(self.blocked_users()?),
+            muted: usernames(self.muted_users()?),
+            sessions: self.tokens()?,
+            identities: self.identities()?,
+            api_keys: self.api_keys()?,
+            media: self.media()?,
+            exported_at: Utc::now().naive_utc(),
+        })
+    }
+
+    /// Deletes the `User` if the deletion is confirmed, together with their
+    /// sessions, keys, identities and media. Their posts and comments are either
+    /// deleted as well or handed over to the `User::DELETED` placeholder.
+    pub fn delete(&self, deletion: AccountDeletion, session: &Token) -> Result<(), ApiError> {
+        let confirmed = match (&deletion.password, &deletion.code) {
+            (Some(password), _) => self.verify_password(password)?,
+            (None, Some(code)) => self.totp_enabled && self.verify_second_factor(code)?,
+            (None, None) => session.recently_reauthenticated(),
+        };
+        if !confirmed {
+            return Err(ApiError::new(
+                403,
+                "Confirm the deletion with your password, a second factor code or by \
+                logging in through a linked identity provider again."
+                    .into(),
+            ));
+        }
+
+        let media = self.media()?;
+        db::connection()?.transaction(|conn| {
+            if deletion.content == DeletedContent::Anonymize {
+                diesel::update(posts::table.filter(posts::author.eq(&self.username)))
+                    .set((
+                        posts::author.eq(Self::DELETED),
+                        // Slugs are only unique per author. They're cut to
+                        // leave room for the id within the column's limit.
+                        posts::slug.eq(sql::<Text>(&format!(
+                            "left(\"slug\", {}) || '-' || \"id\"",
+                            MAX_SLUG_LENGTH,
+                        ))),
+                    ))
+                    .execute(conn)?;
+                diesel::update(comments::table.filter(comments::author.eq(&self.username)))
+                    .set(comments::author.eq(Self::DELETED))
+                    .execute(conn)?;
+            }
+
+            diesel::delete(users::table.filter(users::id.eq(self.id))).execute(conn)
+        })?;
+        media.iter().for_each(Media::remove_files);
+
+        info!("{:?} deleted their account ({:?})", self.username, deletion.content);
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{db, schema::api_keys, ApiError, Token, User};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, str::FromStr};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+/// The minimum time between two updates of an API key's last use.
+const LAST_USED_INTERVAL_MINUTES: i64 = 60;
+
+/// What an API key is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scope {
+    /// Reading data only visible when authenticated.
+    #[serde(rename = "read")]
+    Read,
+    /// Creating, editing and deleting posts.
+    #[serde(rename = "posts:write")]
+    PostsWrite,
+    /// Creating, editing and deleting comments.
+    #[serde(rename = "comments:write")]
+    CommentsWrite,
+    /// Uploading and deleting media.
+    #[serde(rename = "media:write")]
+    MediaWrite,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::PostsWrite => "posts:write",
+            Self::CommentsWrite => "comments:write",
+            Self::MediaWrite => "media:write",
+        }
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "posts:write" => Ok(Self::PostsWrite),
+            "comments:write" => Ok(Self::CommentsWrite),
+            "media:write" => Ok(Self::MediaWrite),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A request to create an API key.
+#[derive(Debug, Deserialize, Validate)]
+pub struct NewApiKey {
+    #[validate(custom = "ApiKey::valid_name")]
+    pub name: String,
+    #[validate(length(min = 1))]
+    pub scopes: Vec<Scope>,
+    /// The number of days the key is valid for. Keys without it never expire.
+    #[serde(rename = "expiresInDays")]
+    #[validate(range(min = 1, max = 3650))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = api_keys)]
+struct InsertableApiKey {
+    user: Uuid,
+    name: String,
+    hash: String,
+    scopes: Vec<String>,
+    expiration: Option<NaiveDateTime>,
+}
+
+/// A long-lived secret for scripts and integrations, limited to a set of
+/// `Scope`s. Like with `Token`s, only a digest of the secret is stored.
+#[derive(Debug, Queryable, Serialize)]
+pub struct ApiKey {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user: Uuid,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub hash: String,
+    pub scopes: Vec<String>,
+    pub expiration: Option<NaiveDateTime>,
+    /// The date the key was last used, accurate to
+    /// `LAST_USED_INTERVAL_MINUTES`.
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: Option<NaiveDateTime>,
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+/// A newly created `ApiKey` together with its secret, which is returned to the
+/// user only this once.
+#[derive(Debug, Serialize)]
+pub struct IssuedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub details: ApiKey,
+}
+
+impl ApiKey {
+    /// The prefix of API key secrets, distinguishing them from session tokens.
+    pub const PREFIX: &'static str = "eph_";
+
+    pub fn valid_name(name: &str) -> Result<(), ValidationError> {
+        if name.trim().is_empty() {
+            return Err(ValidationError::new("Name can't be empty."));
+        }
+        if name.trim().chars().count() > 64 {
+            return Err(ValidationError::new("Name can't be longer than 64 characters."));
+        }
+        Ok(())
+    }
+
+    /// Finds an `ApiKey` by its secret, recording its use.
+    pub fn find(secret: &str) -> Result<Self, ApiError> {
+        let key = api_keys::table
+            .filter(api_keys::hash.eq(Token::hash(secret)))
+            .first::<Self>(&mut db::connection()?)?;
+
+        let now = Utc::now().naive_utc();
+        if key.expiration.is_some_and(|expiration| expiration < now) {
+            return Err(ApiError::new(404, "API key has expired.".into()));
+        }
+
+        let interval = Duration::minutes(LAST_USED_INTERVAL_MINUTES);
+        if key.last_used_at.is_some_and(|last_used| now - last_used < interval) {
+            return Ok(key);
+        }
+
+        Ok(diesel::update(api_keys::table.filter(api_keys::id.eq(key.id)))
+            .set(api_keys::last_used_at.eq(now))
+            .get_result(&mut db::connection()?)?)
+    }
+
+    /// Finds an `ApiKey` by its `id`.
+    pub fn by_id(id: Uuid) -> Result<Self, ApiError> {
+        Ok(api_keys::table
+            .filter(api_keys::id.eq(id))
+            .first(&mut db::connection()?)?)
+    }
+
+    /// Whether the key grants `scope`.
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|s| s.parse() == Ok(scope))
+    }
+
+    /// Deletes the `ApiKey`.
+    pub fn delete(&self) -> Result<Self, ApiError> {
+        Ok(diesel::delete(api_keys::table.filter(api_keys::id.eq(self.id)))
+            .get_result(&mut db::connection()?)?)
+    }
+}
+
+impl TryFrom<(NewApiKey, &User)> for IssuedApiKey {
+    type Error = ApiError;
+
+    fn try_from((key, user): (NewApiKey, &User)) -> Result<Self, Self::Error> {
+        let secret = format!("{}{}", ApiKey::PREFIX, Token::generate_secret());
+        let expiration = match key.expires_in_days {
+            Some(days) => Some(
+                Utc::now()
+                    .naive_utc()
+                    .checked_add_signed(Duration::days(days))
+                    .ok_or_else(|| ApiError::new(
+                        500, "Couldn't compute expiration date of API key".into(),
+                    ))?,
+            ),
+            None => None,
+        };
+        let mut scopes: Vec<String> = key.scopes.iter().map(|s| s.to_string()).collect();
+        scopes.sort();
+        scopes.dedup();
+
+        let details = diesel::insert_into(api_keys::table)
+            .values(InsertableApiKey {
+                user: user.id,
+                name: key.name.trim().into(),
+                hash: Token::hash(&secret),
+                scopes,
+                expiration,
+            })
+            .get_result::<ApiKey>(&mut db::connection()?)?;
+
+        info!("{:?} created API key {:?}", user.username, details.name);
+        Ok(IssuedApiKey {
+            key: secret,
+            details,
+        })
+    }
+}
+
+impl User {
+    /// Returns the `User`'s API keys, newest first.
+    pub fn api_keys(&self) -> Result<Vec<ApiKey>, ApiError> {
+        Ok(api_keys::table
+            .filter(api_keys::user.eq(self.id))
+            .order(api_keys::created_at.desc())
+            .load(&mut db::connection()?)?)
+    }
+}
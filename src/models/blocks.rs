@@ -0,0 +1,157 @@
+use crate::{
+    db,
+    schema::{blocks, follows, mutes, users},
+    ApiError, User,
+};
+use diesel::{pg::Pg, prelude::*, sql_types::Text};
+use uuid::Uuid;
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = blocks)]
+struct NewBlock {
+    blocker: Uuid,
+    blocked: Uuid,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = mutes)]
+struct NewMute {
+    muter: Uuid,
+    muted: Uuid,
+}
+
+impl User {
+    /// Blocks `user`, which ends any follows between the two. A blocked user
+    /// can't see or comment on the `User`'s posts, and the `User` no longer
+    /// sees theirs.
+    pub fn block(&self, user: &User) -> Result<(), ApiError> {
+        if self.id == user.id {
+            return Err(ApiError::new(400, "You can't block yourself.".into()));
+        }
+
+        db::connection()?.transaction(|conn| {
+            diesel::insert_into(blocks::table)
+                .values(NewBlock {
+                    blocker: self.id,
+                    blocked: user.id,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+            diesel::delete(follows::table)
+                .filter(
+                    follows::follower.eq(self.id).and(follows::followee.eq(user.id))
+                        .or(follows::follower.eq(user.id).and(follows::followee.eq(self.id))),
+                )
+                .execute(conn)
+        })?;
+
+        info!("{:?} blocked {:?}", self.username, user.username);
+        Ok(())
+    }
+
+    /// Unblocks `user`.
+    pub fn unblock(&self, user: &User) -> Result<(), ApiError> {
+        diesel::delete(blocks::table)
+            .filter(blocks::blocker.eq(self.id))
+            .filter(blocks::blocked.eq(user.id))
+            .execute(&mut db::connection()?)?;
+
+        Ok(())
+    }
+
+    /// Mutes `user`, hiding their posts and comments from the `User` without
+    /// them noticing.
+    pub fn mute(&self, user: &User) -> Result<(), ApiError> {
+        if self.id == user.id {
+            return Err(ApiError::new(400, "You can't mute yourself.".into()));
+        }
+
+        diesel::insert_into(mutes::table)
+            .values(NewMute {
+                muter: self.id,
+                muted: user.id,
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut db::connection()?)?;
+
+        Ok(())
+    }
+
+    /// Unmutes `user`.
+    pub fn unmute(&self, user: &User) -> Result<(), ApiError> {
+        diesel::delete(mutes::table)
+            .filter(mutes::muter.eq(self.id))
+            .filter(mutes::muted.eq(user.id))
+            .execute(&mut db::connection()?)?;
+
+        Ok(())
+    }
+
+    /// Returns the users the `User` blocked, ordered by username.
+    pub fn blocked_users(&self) -> Result<Vec<User>, ApiError> {
+        let blocked = blocks::table
+            .filter(blocks::blocker.eq(self.id))
+            .select(blocks::blocked);
+        Ok(users::table
+            .filter(users::id.eq_any(blocked))
+            .order(users::username)
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Returns the users the `User` muted, ordered by username.
+    pub fn muted_users(&self) -> Result<Vec<User>, ApiError> {
+        let muted = mutes::table
+            .filter(mutes::muter.eq(self.id))
+            .select(mutes::muted);
+        Ok(users::table
+            .filter(users::id.eq_any(muted))
+            .order(users::username)
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Checks whether the user called `username` blocked the `User`.
+    pub fn blocked_by(&self, username: &str) -> Result<bool, ApiError> {
+        let blockers = blocks::table
+            .filter(blocks::blocked.eq(self.id))
+            .select(blocks::blocker);
+        Ok(diesel::select(diesel::dsl::exists(
+            users::table
+                .filter(users::username.eq(username))
+                .filter(users::id.eq_any(blockers)),
+        ))
+        .get_result(&mut db::connection()?)?)
+    }
+
+    /// Checks whether the content of the user called `username` is hidden
+    /// from the `User`, see `hidden_authors`.
+    pub fn hides(&self, username: &str) -> Result<bool, ApiError> {
+        Ok(diesel::select(diesel::dsl::exists(
+            self.hidden_authors().filter(users::username.eq(username.to_owned())),
+        ))
+        .get_result(&mut db::connection()?)?)
+    }
+
+    /// Returns the usernames of the users whose content is hidden from the
+    /// `User`: the ones they muted or blocked and the ones who blocked them.
+    pub fn hidden_authors(&self) -> users::BoxedQuery<'static, Pg, Text> {
+        let muted = mutes::table
+            .filter(mutes::muter.eq(self.id))
+            .select(mutes::muted);
+        let blocked = blocks::table
+            .filter(blocks::blocker.eq(self.id))
+            .select(blocks::blocked);
+        let blockers = blocks::table
+            .filter(blocks::blocked.eq(self.id))
+            .select(blocks::blocker);
+
+        users::table
+            .select(users::username)
+            .filter(
+                users::id
+                    .eq_any(muted)
+                    .or(users::id.eq_any(blocked))
+                    .or(users::id.eq_any(blockers)),
+            )
+            .into_boxed()
+    }
+}
@@ -1,4 +1,10 @@
-use crate::{schema::comments, ApiError, db::{Paginate, self}, User};
+use crate::{
+    db::{self, Paginate},
+    markdown,
+    models::revisions::NewCommentRevision,
+    schema::{comment_revisions, comments, posts},
+    ApiError, Post, User,
+};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -21,10 +27,12 @@ pub struct Comment {
     pub created_at: NaiveDateTime,
     #[serde(rename = "updatedAt")]
     pub updated_at: Option<NaiveDateTime>,
+    /// The message rendered from Markdown.
+    #[serde(rename = "messageHtml")]
+    pub message_html: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Validate, AsChangeset)]
-#[diesel(table_name = comments)]
+#[derive(Debug, Deserialize, Validate)]
 pub struct UpdateComment {
     pub id: i32,
     #[validate(custom = "Comment::valid_message")]
@@ -40,10 +48,22 @@ impl Comment {
         Ok(())
     }
 
-    /// Returns all comments matching the filters.
-    pub fn find_all(filters: CommentFilters) -> Result<Vec<Self>, ApiError> {
+    /// Returns all comments matching the filters, leaving out the ones
+    /// `viewer` shouldn't see because of blocks or mutes. Comments on posts
+    /// `viewer` can't see aren't found at all.
+    pub fn find_all(
+        filters: CommentFilters, viewer: Option<&User>,
+    ) -> Result<Vec<Self>, ApiError> {
+        Post::find(filters.post, viewer)?;
+
         let mut query = comments::table.filter(comments::post.eq(filters.post))
-            .paginate(filters.offset.unwrap_or(0));
+            .into_boxed();
+
+        if let Some(viewer) = viewer {
+            query = query.filter(comments::author.ne_all(viewer.hidden_authors()));
+        }
+
+        let mut query = query.paginate(filters.offset.unwrap_or(0));
 
         if let Some(limit) = filters.limit {
             query = query.limit(limit)
@@ -54,18 +74,74 @@ impl Comment {
         Ok(comments)
     }
 
-    /// Finds a comments by its id.
-    pub fn find(id: i32) -> Result<Self, ApiError> {
-        Ok(comments::table
-           .filter(comments::id.eq(id))
-           .first(&mut db::connection()?)?)
+    /// Finds a comment by its id, as long as `viewer` wrote it or may see both
+    /// the post it's on and its author.
+    pub fn find(id: i32, viewer: Option<&User>) -> Result<Self, ApiError> {
+        let comment: Self = comments::table
+            .filter(comments::id.eq(id))
+            .first(&mut db::connection()?)?;
+
+        if viewer.is_some_and(|viewer| viewer.username == comment.author) {
+            return Ok(comment);
+        }
+        Post::find(comment.post, viewer)?;
+        if let Some(viewer) = viewer {
+            if viewer.hides(&comment.author)? {
+                return Err(ApiError::new(404, "Record not found".into()));
+            }
+        }
+        Ok(comment)
     }
 
-    /// Updates the comment with the supplied new comment.
-    pub fn edit(&self, update: UpdateComment) -> Result<Self, ApiError> {
-        Ok(diesel::update(comments::table.filter(comments::id.eq(self.id)))
-           .set(update)
-           .get_result(&mut db::connection()?)?)
+    /// Finds a comment by its id for `editor` to change, without hiding it
+    /// because of blocks or mutes, like `Post::find_for_edit`.
+    pub fn find_for_edit(id: i32, editor: &User) -> Result<Self, ApiError> {
+        let comment: Self = comments::table
+            .filter(comments::id.eq(id))
+            .first(&mut db::connection()?)?;
+
+        if editor.username != comment.author {
+            Post::find_for_edit(comment.post, editor)?;
+        }
+        Ok(comment)
+    }
+
+    /// Updates the comment with the supplied new comment, keeping the
+    /// previous version in its history if the message changed.
+    pub fn edit(&self, update: UpdateComment, editor: &User) -> Result<Self, ApiError> {
+        let comment = db::connection()?.transaction(|conn| {
+            let comment: Self = diesel::update(comments::table.filter(comments::id.eq(self.id)))
+                .set((
+                    comments::message_html.eq(markdown::render(&update.message)),
+                    comments::message.eq(update.message),
+                ))
+                .get_result(conn)?;
+
+            if comment.message != self.message {
+                diesel::insert_into(comment_revisions::table)
+                    .values(NewCommentRevision::of(&comment, editor))
+                    .execute(conn)?;
+            }
+            Ok::<_, diesel::result::Error>(comment)
+        })?;
+
+        Ok(comment)
+    }
+
+    /// Renders the messages of comments that weren't rendered when written.
+    pub fn render_missing() -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+        let comments: Vec<(i32, String)> = comments::table
+            .filter(comments::message_html.is_null())
+            .select((comments::id, comments::message))
+            .load(&mut conn)?;
+
+        for (id, message) in &comments {
+            diesel::update(comments::table.filter(comments::id.eq(id)))
+                .set(comments::message_html.eq(markdown::render(message)))
+                .execute(&mut conn)?;
+        }
+        Ok(comments.len())
     }
 
     /// Deletes the comment.
@@ -81,13 +157,29 @@ impl TryFrom<(NewComment, &User)> for Comment {
     fn try_from(
         (comment, author): (NewComment, &User)
     ) -> Result<Self, Self::Error> {
-        let comment = diesel::insert_into(comments::table)
-            .values(&InsertableComment {
-                author: author.username.to_owned(),
-                post: comment.post,
-                message: comment.message.trim().into(),
-            })
-            .get_result::<Comment>(&mut db::connection()?)?;
+        let post_author: String = posts::table
+            .find(comment.post)
+            .select(posts::author)
+            .first(&mut db::connection()?)?;
+        if author.blocked_by(&post_author)? {
+            return Err(ApiError::new(403, "You can't comment on this post.".into()));
+        }
+        Post::find(comment.post, Some(author))?;
+
+        let comment = db::connection()?.transaction(|conn| {
+            let comment = diesel::insert_into(comments::table)
+                .values(&InsertableComment {
+                    author: author.username.to_owned(),
+                    post: comment.post,
+                    message: comment.message.trim().into(),
+                    message_html: markdown::render(comment.message.trim()),
+                })
+                .get_result::<Comment>(conn)?;
+            diesel::insert_into(comment_revisions::table)
+                .values(NewCommentRevision::of(&comment, author))
+                .execute(conn)?;
+            Ok::<_, diesel::result::Error>(comment)
+        })?;
 
         info!("{:?} posted comment {} on post #{}", author.username, comment.id,
               comment.post);
@@ -101,6 +193,7 @@ pub struct InsertableComment {
     pub author: String,
     pub post: i32,
     pub message: String,
+    pub message_html: String,
 }
 
 /// Filters to be applied to a comment search.
@@ -0,0 +1,203 @@
+use crate::{
+    config, db,
+    mail::{self, Mail},
+    schema::users,
+    ApiError, Token, User,
+};
+use chrono::{Duration, Utc};
+use data_encoding::BASE64URL_NOPAD;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+lazy_static! {
+    /// Whether every account must have an email address.
+    pub static ref EMAIL_REQUIRED: bool = config::var_or("EMAIL_REQUIRED", false);
+    /// How long an email verification link stays valid.
+    static ref EMAIL_VERIFICATION_LIFETIME: Duration =
+        Duration::hours(config::var_or("EMAIL_VERIFICATION_HOURS", 24));
+    /// The URL of the page users verify their email address on. The signed
+    /// token is appended as the `token` query parameter.
+    static ref EMAIL_VERIFICATION_URL: String = config::var_or(
+        "EMAIL_VERIFICATION_URL",
+        "http://localhost:3000/verify-email".to_string(),
+    );
+    /// The key verification links are signed with. Without a configured key,
+    /// links only stay valid until the server restarts.
+    static ref EMAIL_VERIFICATION_SECRET: String = {
+        let secret: String = config::var_or("EMAIL_VERIFICATION_SECRET", String::new());
+        if secret.is_empty() {
+            warn!("EMAIL_VERIFICATION_SECRET isn't set, using a random one");
+            Token::generate_secret()
+        } else {
+            secret
+        }
+    };
+}
+
+/// Verifies an email address using the token from a verification link.
+#[derive(Debug, Deserialize)]
+pub struct EmailVerification {
+    pub token: String,
+}
+
+/// The contents of a signed email verification link. No state is stored on
+/// the server; a link only verifies the address it was sent to and expires on
+/// its own.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmailClaims {
+    pub user: Uuid,
+    pub email: String,
+    /// The UNIX timestamp the link expires at.
+    pub exp: i64,
+}
+
+impl EmailClaims {
+    /// Serializes and signs the claims with HMAC-SHA256 using `key`.
+    pub fn sign(&self, key: &[u8]) -> String {
+        let payload = BASE64URL_NOPAD.encode(
+            &serde_json::to_vec(self).expect("Couldn't serialize email claims"),
+        );
+        let signature = BASE64URL_NOPAD.encode(&Self::mac(key, &payload).finalize().into_bytes());
+        format!("{}.{}", payload, signature)
+    }
+
+    /// Returns the claims of a token created by `sign` if its signature is
+    /// valid and it hasn't expired at `now`.
+    ///
+    /// ```
+    /// use ephemeris::EmailClaims;
+    /// use uuid::Uuid;
+    ///
+    /// let claims = EmailClaims {
+    ///     user: Uuid::nil(),
+    ///     email: "jane@example.com".into(),
+    ///     exp: 1000,
+    /// };
+    /// let token = claims.sign(b"key");
+    ///
+    /// assert_eq!(EmailClaims::verify(&token, b"key", 999), Some(claims));
+    /// assert_eq!(EmailClaims::verify(&token, b"key", 1000), None);
+    /// assert_eq!(EmailClaims::verify(&token, b"other key", 999), None);
+    /// assert_eq!(EmailClaims::verify(&token.replace('.', "x."), b"key", 999), None);
+    /// ```
+    pub fn verify(token: &str, key: &[u8], now: i64) -> Option<Self> {
+        let (payload, signature) = token.split_once('.')?;
+        let signature = BASE64URL_NOPAD.decode(signature.as_bytes()).ok()?;
+        Self::mac(key, payload).verify_slice(&signature).ok()?;
+
+        let claims: Self =
+            serde_json::from_slice(&BASE64URL_NOPAD.decode(payload.as_bytes()).ok()?).ok()?;
+        Some(claims).filter(|claims| claims.exp > now)
+    }
+
+    fn mac(key: &[u8], payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+impl User {
+    /// Checks whether another `User` uses the given email address.
+    pub fn email_taken(email: &str) -> Result<bool, ApiError> {
+        Ok(diesel::select(diesel::dsl::exists(
+            users::table.filter(
+                diesel::dsl::sql::<diesel::sql_types::Text>("lower(\"email\")")
+                    .eq(email.to_lowercase()),
+            ),
+        ))
+        .get_result(&mut db::connection()?)?)
+    }
+
+    /// Mails a verification link for the `User`'s email address to them.
+    pub fn send_email_verification(&self) -> Result<(), ApiError> {
+        let email = self
+            .email
+            .as_ref()
+            .ok_or_else(|| ApiError::new(400, "No email address set.".into()))?;
+        if self.email_verified {
+            return Err(ApiError::new(409, "Email address already verified.".into()));
+        }
+
+        let token = EmailClaims {
+            user: self.id,
+            email: email.to_owned(),
+            exp: (Utc::now() + *EMAIL_VERIFICATION_LIFETIME).timestamp(),
+        }
+        .sign(EMAIL_VERIFICATION_SECRET.as_bytes());
+
+        mail::send(Mail {
+            to: email.to_owned(),
+            subject: "Verify your email address".into(),
+            body: format!(
+                "Please confirm that {} is the email address of your account {:?} \
+                by following this link within the next {} hours:\n\n\
+                {}?token={}\n\n\
+                If you didn't sign up, you can ignore this mail.",
+                email,
+                self.username,
+                EMAIL_VERIFICATION_LIFETIME.num_hours(),
+                *EMAIL_VERIFICATION_URL,
+                token,
+            ),
+        })?;
+
+        info!("Sent email verification to {:?}", self.username);
+        Ok(())
+    }
+
+    /// Sends the verification mail without failing the request that changed
+    /// the email address if it can't be delivered; it can be resent later.
+    pub(crate) fn try_send_email_verification(&self) {
+        if let Err(e) = self.send_email_verification() {
+            error!("Couldn't send email verification to {:?}: {}", self.username, e);
+        }
+    }
+
+    /// Lets the owner of `email` know that someone tried to register another
+    /// account with it, instead of telling the person registering that the
+    /// address is taken.
+    pub(crate) fn notify_email_in_use(email: &str, username: &str) {
+        let result = mail::send(Mail {
+            to: email.to_owned(),
+            subject: "Your email address is already in use".into(),
+            body: format!(
+                "Someone just registered the account {:?} with this email address, \
+                but it already belongs to another account. The address wasn't added \
+                to the new account.\n\n\
+                If it was you and you forgot your password, you can reset it instead. \
+                Otherwise, you can ignore this mail.",
+                username,
+            ),
+        });
+        if let Err(e) = result {
+            error!("Couldn't send email address in use notice: {}", e);
+        }
+    }
+
+    /// Marks the email address in a verification link as verified, as long as
+    /// it's still the address of the `User` it was sent to.
+    pub fn verify_email(verification: EmailVerification) -> Result<Self, ApiError> {
+        let invalid = || ApiError::new(400, "Invalid or expired verification link.".into());
+        let claims = EmailClaims::verify(
+            &verification.token,
+            EMAIL_VERIFICATION_SECRET.as_bytes(),
+            Utc::now().timestamp(),
+        )
+        .ok_or_else(invalid)?;
+
+        let user: Self = diesel::update(users::table)
+            .filter(users::id.eq(claims.user))
+            .filter(users::email.eq(&claims.email))
+            .set(users::email_verified.eq(true))
+            .get_result(&mut db::connection()?)
+            .map_err(|_| invalid())?;
+
+        info!("{:?} verified their email address", user.username);
+        Ok(user)
+    }
+}
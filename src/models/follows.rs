@@ -0,0 +1,115 @@
+use crate::{
+    db::{self, Direction, Paginate, Pagination},
+    schema::{follows, posts, users},
+    ApiError, Post, PostStatus, User,
+};
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = follows)]
+struct NewFollow {
+    follower: Uuid,
+    followee: Uuid,
+}
+
+impl User {
+    /// Makes the `User` follow `author`, doing nothing if they already do.
+    pub fn follow(&self, author: &User) -> Result<(), ApiError> {
+        if self.id == author.id {
+            return Err(ApiError::new(400, "You can't follow yourself.".into()));
+        }
+        if self.blocked_by(&author.username)? {
+            return Err(ApiError::new(403, "You can't follow this user.".into()));
+        }
+
+        diesel::insert_into(follows::table)
+            .values(NewFollow {
+                follower: self.id,
+                followee: author.id,
+            })
+            .on_conflict_do_nothing()
+            .execute(&mut db::connection()?)?;
+
+        Ok(())
+    }
+
+    /// Makes the `User` stop following `author`.
+    pub fn unfollow(&self, author: &User) -> Result<(), ApiError> {
+        diesel::delete(follows::table)
+            .filter(follows::follower.eq(self.id))
+            .filter(follows::followee.eq(author.id))
+            .execute(&mut db::connection()?)?;
+
+        Ok(())
+    }
+
+    /// Returns the users following the `User`, ordered by username.
+    pub fn followers(&self, page: Pagination) -> Result<Vec<User>, ApiError> {
+        let followers = follows::table
+            .filter(follows::followee.eq(self.id))
+            .select(follows::follower);
+        Self::page(users::table.filter(users::id.eq_any(followers)).into_boxed(), page)
+    }
+
+    /// Returns the users the `User` follows, ordered by username.
+    pub fn following(&self, page: Pagination) -> Result<Vec<User>, ApiError> {
+        let followees = follows::table
+            .filter(follows::follower.eq(self.id))
+            .select(follows::followee);
+        Self::page(users::table.filter(users::id.eq_any(followees)).into_boxed(), page)
+    }
+
+    fn page(
+        query: users::BoxedQuery<'static, diesel::pg::Pg>, page: Pagination,
+    ) -> Result<Vec<User>, ApiError> {
+        let mut query = query
+            .paginate(page.offset.unwrap_or(0))
+            .column("username")
+            .direction(Direction::ASC);
+
+        if let Some(limit) = page.limit {
+            query = query.limit(limit)
+        }
+
+        Ok(query.get_results(&mut db::connection()?)?)
+    }
+
+    /// Returns the number of users following the `User` and the number of
+    /// users they follow.
+    pub fn follow_counts(&self) -> Result<(i64, i64), ApiError> {
+        let mut conn = db::connection()?;
+        let followers = follows::table
+            .filter(follows::followee.eq(self.id))
+            .count()
+            .get_result(&mut conn)?;
+        let following = follows::table
+            .filter(follows::follower.eq(self.id))
+            .count()
+            .get_result(&mut conn)?;
+
+        Ok((followers, following))
+    }
+
+    /// Returns the posts of the authors the `User` follows and hasn't muted,
+    /// newest first.
+    pub fn feed(&self, page: Pagination) -> Result<Vec<Post>, ApiError> {
+        let authors = follows::table
+            .inner_join(users::table.on(users::id.eq(follows::followee)))
+            .filter(follows::follower.eq(self.id))
+            .select(users::username);
+        let mut query = posts::table
+            .filter(posts::author.eq_any(authors))
+            .filter(posts::author.ne_all(self.hidden_authors()))
+            .filter(posts::status.eq(PostStatus::Published))
+            .into_boxed()
+            .paginate(page.offset.unwrap_or(0))
+            .column("published_at");
+
+        if let Some(limit) = page.limit {
+            query = query.limit(limit)
+        }
+
+        Ok(query.get_results(&mut db::connection()?)?)
+    }
+}
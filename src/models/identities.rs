@@ -0,0 +1,302 @@
+use crate::{
+    db,
+    oidc::{AuthorizationRequest, Claims},
+    schema::{identities, oidc_states, users},
+    ApiError, Registration, Token, User, EMAIL_REQUIRED,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The parameters the identity provider redirects the user back with.
+#[derive(Debug, Deserialize)]
+pub struct OidcCallback {
+    pub code: String,
+    pub state: String,
+}
+
+/// A link between a `User` and their account at an identity provider.
+#[derive(Debug, Queryable, Serialize)]
+pub struct Identity {
+    #[serde(skip_serializing)]
+    pub id: i32,
+    #[serde(skip_serializing)]
+    pub user: Uuid,
+    pub provider: String,
+    #[serde(skip_serializing)]
+    pub subject: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = identities)]
+struct NewIdentity<'a> {
+    user: Uuid,
+    provider: &'a str,
+    subject: &'a str,
+}
+
+/// A pending authorization request, identified by the digest of its `state`.
+#[derive(Debug, Queryable)]
+pub struct OidcState {
+    pub hash: String,
+    pub provider: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    pub expiration: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = oidc_states)]
+struct NewOidcState<'a> {
+    hash: String,
+    provider: &'a str,
+    nonce: &'a str,
+    code_verifier: &'a str,
+    expiration: NaiveDateTime,
+}
+
+impl OidcState {
+    /// How long a user has to authorize at the identity provider.
+    pub const MINUTES: i64 = 10;
+
+    /// Remembers an authorization request until the user returns.
+    pub fn save(provider: &str, request: &AuthorizationRequest) -> Result<(), ApiError> {
+        let expiration = Utc::now()
+            .naive_utc()
+            .checked_add_signed(Duration::minutes(Self::MINUTES))
+            .ok_or_else(|| ApiError::new(
+                500, "Couldn't compute expiration date of authorization request".into(),
+            ))?;
+
+        diesel::insert_into(oidc_states::table)
+            .values(NewOidcState {
+                hash: Token::hash(&request.state),
+                provider,
+                nonce: &request.nonce,
+                code_verifier: &request.code_verifier,
+                expiration,
+            })
+            .execute(&mut db::connection()?)?;
+
+        Ok(())
+    }
+
+    /// Deletes the authorization requests users never returned from.
+    pub fn delete_expired() -> Result<usize, ApiError> {
+        Ok(diesel::delete(oidc_states::table)
+            .filter(oidc_states::expiration.lt(Utc::now().naive_utc()))
+            .execute(&mut db::connection()?)?)
+    }
+
+    /// Removes and returns the authorization request with the given `state`,
+    /// so it can only be completed once.
+    pub fn take(provider: &str, state: &str) -> Result<Self, ApiError> {
+        let invalid = || ApiError::new(400, "Unknown or expired authorization request.".into());
+        let state = diesel::delete(oidc_states::table)
+            .filter(oidc_states::hash.eq(Token::hash(state)))
+            .filter(oidc_states::provider.eq(provider))
+            .get_result::<Self>(&mut db::connection()?)
+            .map_err(|_| invalid())?;
+
+        if state.expiration < Utc::now().naive_utc() {
+            return Err(invalid());
+        }
+
+        Ok(state)
+    }
+}
+
+impl User {
+    /// Returns the `User` linked to the identity described by `claims`,
+    /// creating an account on the first login. New accounts get the email
+    /// address the provider shares, which needs to be verified unless the
+    /// provider has already done so.
+    pub fn from_identity(provider: &str, claims: &Claims) -> Result<Self, ApiError> {
+        let identity = identities::table
+            .filter(identities::provider.eq(provider))
+            .filter(identities::subject.eq(&claims.subject))
+            .first::<Identity>(&mut db::connection()?)
+            .optional()?;
+
+        if let Some(identity) = identity {
+            return Self::find(identity.user);
+        }
+
+        let email = claims
+            .email
+            .as_deref()
+            .map(str::trim)
+            .filter(|email| !email.is_empty());
+        match email {
+            // Without saying why, so identity providers can't be used to find
+            // out which addresses have accounts.
+            Some(email) if User::email_taken(email)? => {
+                return Err(ApiError::new(
+                    409,
+                    "Couldn't create an account for this identity. If you already have \
+                    an account, log in and link the identity to it instead."
+                        .into(),
+                ));
+            }
+            None if *EMAIL_REQUIRED => {
+                return Err(ApiError::new(
+                    400, "The identity provider didn't share an email address.".into(),
+                ));
+            }
+            _ => {}
+        }
+
+        let username = Self::available_username(claims)?;
+        // The account can only be logged into through the identity provider
+        // until a password is set using a password reset.
+        let password = Self::hash_password(&Token::generate_secret())?;
+
+        let mut conn = db::connection()?;
+        let user = conn.transaction(|conn| {
+            let user = diesel::insert_into(users::table)
+                .values((
+                    Registration {
+                        username,
+                        password,
+                        email: email.map(str::to_owned),
+                    },
+                    users::email_verified.eq(email.is_some() && claims.email_verified),
+                ))
+                .get_result::<Self>(conn)?;
+            diesel::insert_into(identities::table)
+                .values(NewIdentity {
+                    user: user.id,
+                    provider,
+                    subject: &claims.subject,
+                })
+                .execute(conn)?;
+            QueryResult::Ok(user)
+        })?;
+
+        info!("Registered {:?} through {}", user.username, provider);
+        if user.email.is_some() && !user.email_verified {
+            user.try_send_email_verification();
+        }
+
+        Ok(user)
+    }
+
+    /// Links the identity described by `claims` to the `User`, so they can
+    /// log in through the provider.
+    pub fn link_identity(&self, provider: &str, claims: &Claims) -> Result<Identity, ApiError> {
+        let linked = identities::table
+            .filter(identities::provider.eq(provider))
+            .filter(identities::subject.eq(&claims.subject))
+            .first::<Identity>(&mut db::connection()?)
+            .optional()?;
+        match linked {
+            Some(identity) if identity.user == self.id => {
+                return Err(ApiError::new(409, "Identity already linked.".into()));
+            }
+            Some(_) => {
+                return Err(ApiError::new(
+                    409, "Identity already linked to another account.".into(),
+                ));
+            }
+            None => {}
+        }
+
+        let identity = diesel::insert_into(identities::table)
+            .values(NewIdentity {
+                user: self.id,
+                provider,
+                subject: &claims.subject,
+            })
+            .get_result(&mut db::connection()?)?;
+        info!("{:?} linked an identity at {}", self.username, provider);
+        Ok(identity)
+    }
+
+    /// Checks whether the identity described by `claims` is linked to the
+    /// `User`.
+    pub fn has_identity(&self, provider: &str, claims: &Claims) -> Result<bool, ApiError> {
+        Ok(diesel::select(diesel::dsl::exists(
+            identities::table
+                .filter(identities::user.eq(self.id))
+                .filter(identities::provider.eq(provider))
+                .filter(identities::subject.eq(&claims.subject)),
+        ))
+        .get_result(&mut db::connection()?)?)
+    }
+
+    /// Returns the identities linked to the `User`.
+    pub fn identities(&self) -> Result<Vec<Identity>, ApiError> {
+        Ok(identities::table
+            .filter(identities::user.eq(self.id))
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Picks an unused username based on the claims of an identity.
+    fn available_username(claims: &Claims) -> Result<String, ApiError> {
+        let candidates = [
+            claims.preferred_username.as_deref(),
+            claims.email.as_deref().and_then(|email| email.split('@').next()),
+            claims.name.as_deref(),
+        ];
+        let base = candidates
+            .into_iter()
+            .flatten()
+            .find_map(Self::sanitize_username)
+            .unwrap_or_else(|| "user".to_string());
+
+        if !Self::username_taken(&base)? {
+            return Ok(base);
+        }
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let suffix = rng.gen_range(1..10_000).to_string();
+            let mut username: String = base.chars().take(20 - suffix.len()).collect();
+            username.push_str(&suffix);
+            if Self::valid_username(&username).is_ok() && !Self::username_taken(&username)? {
+                return Ok(username);
+            }
+        }
+
+        Err(ApiError::new(500, "Couldn't find an available username".into()))
+    }
+
+    /// Turns an arbitrary name into a valid username, if possible, by
+    /// lowercasing it, replacing unsupported characters with underscores and
+    /// collapsing consecutive separators.
+    ///
+    /// ```
+    /// use ephemeris::User;
+    ///
+    /// assert_eq!(User::sanitize_username("Jane Doe").as_deref(), Some("jane_doe"));
+    /// assert_eq!(User::sanitize_username("j.-doe").as_deref(), Some("j.doe"));
+    /// assert_eq!(
+    ///     User::sanitize_username("a_very_long_name_indeed").as_deref(),
+    ///     Some("a_very_long_name_ind"),
+    /// );
+    /// assert_eq!(User::sanitize_username("ab"), None);
+    /// ```
+    pub fn sanitize_username(name: &str) -> Option<String> {
+        let mut username = String::new();
+        for c in name.trim().chars().map(|c| c.to_ascii_lowercase()) {
+            let c = if c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' {
+                c
+            } else {
+                '_'
+            };
+            let separator = |c: char| c == '.' || c == '_';
+            if separator(c) && username.ends_with(separator) {
+                continue;
+            }
+            username.push(c);
+        }
+
+        let username: String = username.chars().take(20).collect();
+        Self::valid_username(&username).ok().map(|_| username)
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    db,
+    images::{self, ProcessedImage},
+    schema::{media, users},
+    storage::storage,
+    ApiError, User,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// An image uploaded by a user, stored together with its thumbnails.
+#[derive(Debug, Queryable)]
+pub struct Media {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub content_type: String,
+    pub extension: String,
+    /// The size of the stored image in bytes.
+    pub size: i32,
+    pub width: i32,
+    pub height: i32,
+    /// The sizes of the generated thumbnails (see `images::THUMBNAIL_SIZES`).
+    pub thumbnails: Vec<i32>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = media)]
+struct NewMedia<'a> {
+    id: Uuid,
+    user: Uuid,
+    content_type: &'a str,
+    extension: &'a str,
+    size: i32,
+    width: i32,
+    height: i32,
+    thumbnails: Vec<i32>,
+}
+
+impl Media {
+    /// Processes and stores an uploaded image for `user`.
+    pub fn upload(user: &User, data: &[u8]) -> Result<Self, ApiError> {
+        let image = images::process(data)?;
+        let id = Uuid::new_v4();
+        let thumbnails: Vec<i32> = image.thumbnails.iter().map(|(size, _)| *size as i32).collect();
+        let clean_up = || Self::remove(id, image.extension, thumbnails.iter().copied());
+
+        Self::store(id, &image).inspect_err(|_| clean_up())?;
+        let media = diesel::insert_into(media::table)
+            .values(NewMedia {
+                id,
+                user: user.id,
+                content_type: image.content_type,
+                extension: image.extension,
+                size: image.data.len() as i32,
+                width: image.width as i32,
+                height: image.height as i32,
+                thumbnails: thumbnails.clone(),
+            })
+            .get_result::<Self>(&mut db::connection()?)
+            .inspect_err(|_| clean_up())?;
+
+        info!("{:?} uploaded {} ({}x{})", user.username, media.id, media.width, media.height);
+        Ok(media)
+    }
+
+    /// Finds `Media` by its id.
+    pub fn find(id: Uuid) -> Result<Self, ApiError> {
+        Ok(media::table
+            .filter(media::id.eq(id))
+            .first(&mut db::connection()?)?)
+    }
+
+    /// Returns the storage key of the image, or of the thumbnail with the
+    /// given size.
+    fn key(id: Uuid, extension: &str, size: Option<i32>) -> String {
+        match size {
+            Some(size) => format!("{}/{}.{}", id, size, extension),
+            None => format!("{}/original.{}", id, extension),
+        }
+    }
+
+    fn store(id: Uuid, image: &ProcessedImage) -> Result<(), ApiError> {
+        storage().put(&Self::key(id, image.extension, None), &image.data)?;
+        for (size, data) in &image.thumbnails {
+            storage().put(&Self::key(id, image.extension, Some(*size as i32)), data)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the files of an image from storage, logging failures.
+    fn remove(id: Uuid, extension: &str, thumbnails: impl Iterator<Item = i32>) {
+        for size in std::iter::once(None).chain(thumbnails.map(Some)) {
+            let key = Self::key(id, extension, size);
+            if let Err(e) = storage().delete(&key) {
+                warn!("Couldn't delete {:?} from storage: {}", key, e);
+            }
+        }
+    }
+
+    /// Removes the files of the `Media` from storage, once its row is gone.
+    pub(crate) fn remove_files(&self) {
+        Self::remove(self.id, &self.extension, self.thumbnails.iter().copied());
+    }
+
+    /// Returns the public URL of the image.
+    pub fn url(&self) -> String {
+        storage().url(&Self::key(self.id, &self.extension, None))
+    }
+
+    /// Returns the public URL of the smallest thumbnail at least `size` pixels
+    /// large, or of the image itself if it's smaller than that.
+    pub fn thumbnail_url(&self, size: i32) -> String {
+        match self.thumbnails.iter().filter(|s| **s >= size).min() {
+            Some(size) => storage().url(&Self::key(self.id, &self.extension, Some(*size))),
+            None => self.url(),
+        }
+    }
+
+    /// Deletes the `Media` and its files, removing it as its uploader's
+    /// avatar.
+    pub fn delete(&self) -> Result<(), ApiError> {
+        let mut urls = vec![self.url()];
+        urls.extend(self.thumbnails.iter().map(|size| self.thumbnail_url(*size)));
+
+        let mut conn = db::connection()?;
+        conn.transaction(|conn| {
+            diesel::update(users::table.filter(users::id.eq(self.user)))
+                .filter(users::avatar_url.eq_any(&urls))
+                .set(users::avatar_url.eq(None::<String>))
+                .execute(conn)?;
+            diesel::delete(media::table.filter(media::id.eq(self.id))).execute(conn)
+        })?;
+        self.remove_files();
+
+        Ok(())
+    }
+}
+
+impl Serialize for Media {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Json<'a> {
+            id: Uuid,
+            #[serde(rename = "contentType")]
+            content_type: &'a str,
+            size: i32,
+            width: i32,
+            height: i32,
+            url: String,
+            /// The thumbnail URLs by their size.
+            thumbnails: BTreeMap<i32, String>,
+            #[serde(rename = "createdAt")]
+            created_at: NaiveDateTime,
+        }
+
+        Json {
+            id: self.id,
+            content_type: &self.content_type,
+            size: self.size,
+            width: self.width,
+            height: self.height,
+            url: self.url(),
+            thumbnails: self
+                .thumbnails
+                .iter()
+                .map(|size| (*size, self.thumbnail_url(*size)))
+                .collect(),
+            created_at: self.created_at,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl User {
+    /// Returns the `Media` uploaded by the `User`, newest first.
+    pub fn media(&self) -> Result<Vec<Media>, ApiError> {
+        Ok(media::table
+            .filter(media::user.eq(self.id))
+            .order(media::created_at.desc())
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Uploads an image and makes a thumbnail of it the `User`'s avatar.
+    pub fn set_avatar(&self, data: &[u8]) -> Result<Self, ApiError> {
+        let media = Media::upload(self, data)?;
+        Ok(diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::avatar_url.eq(media.thumbnail_url(256)))
+            .get_result(&mut db::connection()?)?)
+    }
+}
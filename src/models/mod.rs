@@ -1,7 +1,37 @@
 mod posts;
 mod users;
 mod comments;
+mod tokens;
+mod password_resets;
+mod two_factor;
+mod identities;
+mod api_keys;
+mod email_verifications;
+mod accounts;
+mod username_changes;
+mod profiles;
+mod media;
+mod follows;
+mod blocks;
+mod revisions;
+mod tags;
+mod slugs;
+mod search;
 
 pub use posts::*;
 pub use users::*;
 pub use comments::*;
+pub use tokens::*;
+pub use password_resets::*;
+pub use two_factor::*;
+pub use identities::*;
+pub use api_keys::*;
+pub use email_verifications::*;
+pub use accounts::*;
+pub use username_changes::*;
+pub use profiles::*;
+pub use media::*;
+pub use revisions::*;
+pub use tags::*;
+pub use slugs::*;
+pub use search::*;
@@ -0,0 +1,140 @@
+use crate::{
+    config, db,
+    mail::{self, Mail},
+    schema::password_resets,
+    ApiError, Token, User,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+
+lazy_static! {
+    /// How long a password reset link stays valid.
+    static ref PASSWORD_RESET_LIFETIME: Duration =
+        Duration::minutes(config::var_or("PASSWORD_RESET_MINUTES", 60));
+    /// The URL of the page users reset their password on. The secret is
+    /// appended as the `token` query parameter.
+    static ref PASSWORD_RESET_URL: String = config::var_or(
+        "PASSWORD_RESET_URL",
+        "http://localhost:3000/reset-password".to_string(),
+    );
+}
+
+/// A request to change the password of the authenticated user.
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordChange {
+    #[serde(rename = "currentPassword")]
+    pub current_password: String,
+    #[serde(rename = "newPassword")]
+    #[validate(length(min = 6))]
+    pub new_password: String,
+}
+
+/// A request to send a password reset link to a user.
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    pub username: String,
+}
+
+/// Sets a new password using the secret from a password reset link.
+#[derive(Debug, Deserialize, Validate)]
+pub struct PasswordResetConfirmation {
+    pub token: String,
+    #[validate(length(min = 6))]
+    pub password: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = password_resets)]
+struct NewPasswordReset {
+    user: Uuid,
+    hash: String,
+    expiration: NaiveDateTime,
+}
+
+/// A single-use secret allowing a user to set a new password without knowing
+/// the current one. Like with `Token`s, only a digest of the secret is stored.
+#[derive(Debug, Queryable)]
+pub struct PasswordReset {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub hash: String,
+    pub expiration: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+impl PasswordReset {
+    /// Creates a password reset for `user` and mails the link to them if they
+    /// have a verified email address.
+    pub fn send(user: &User) -> Result<(), ApiError> {
+        // Only mail verified addresses, so nobody can hijack an account by
+        // registering it with their own unverified address first.
+        let email = match &user.email {
+            Some(email) if user.email_verified => email,
+            _ => {
+                info!("Not sending password reset to {:?} without email", user.username);
+                return Ok(());
+            }
+        };
+
+        let secret = Token::generate_secret();
+        let expiration = Utc::now()
+            .naive_utc()
+            .checked_add_signed(*PASSWORD_RESET_LIFETIME)
+            .ok_or_else(|| ApiError::new(
+                500, "Couldn't compute expiration date of password reset".into(),
+            ))?;
+
+        diesel::insert_into(password_resets::table)
+            .values(NewPasswordReset {
+                user: user.id,
+                hash: Token::hash(&secret),
+                expiration,
+            })
+            .execute(&mut db::connection()?)?;
+
+        mail::send(Mail {
+            to: email.to_owned(),
+            subject: "Reset your password".into(),
+            body: format!(
+                "Someone requested to reset the password of your account {:?}.\n\
+                If it was you, follow this link within the next {} minutes:\n\n\
+                {}?token={}\n\n\
+                Otherwise you can ignore this mail.",
+                user.username,
+                PASSWORD_RESET_LIFETIME.num_minutes(),
+                *PASSWORD_RESET_URL,
+                secret,
+            ),
+        })?;
+
+        info!("Sent password reset to {:?}", user.username);
+        Ok(())
+    }
+
+    /// Sets the new password of the user the reset belongs to, consuming the
+    /// reset and revoking all of the user's sessions.
+    pub fn confirm(confirmation: PasswordResetConfirmation) -> Result<User, ApiError> {
+        let invalid = || ApiError::new(400, "Invalid or expired reset token.".into());
+        let reset = diesel::delete(password_resets::table)
+            .filter(password_resets::hash.eq(Token::hash(&confirmation.token)))
+            .get_result::<Self>(&mut db::connection()?)
+            .map_err(|_| invalid())?;
+
+        if reset.expiration < Utc::now().naive_utc() {
+            return Err(invalid());
+        }
+
+        let user = User::find(reset.user)?.set_password(&confirmation.password)?;
+        diesel::delete(password_resets::table)
+            .filter(password_resets::user.eq(user.id))
+            .execute(&mut db::connection()?)?;
+        user.revoke_tokens(None)?;
+
+        info!("{:?} reset their password", user.username);
+        Ok(user)
+    }
+}
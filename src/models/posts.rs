@@ -1,19 +1,158 @@
-use crate::db::Paginate;
-use crate::schema::posts;
-use crate::{db, ApiError, User};
-use chrono::NaiveDateTime;
-use diesel::prelude::*;
+use crate::db::{Direction, Paginate};
+use crate::models::revisions::NewPostRevision;
+use crate::schema::{post_revisions, posts};
+use crate::{db, markdown, ApiError, TagMatch, User};
+use chrono::{NaiveDateTime, Utc};
+use diesel::{
+    deserialize::{self, FromSql},
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Text,
+    AsExpression, FromSqlRow,
+};
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 use validator::{Validate, ValidationError};
 
-#[derive(Debug, Deserialize, Validate, AsChangeset)]
-#[diesel(table_name = posts)]
+/// Who can see a post.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsExpression, FromSqlRow, Serialize, Deserialize)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    /// Work in progress, only visible to its author.
+    Draft,
+    /// Only visible to its author until it's published at `published_at`.
+    Scheduled,
+    /// Visible to everyone.
+    Published,
+}
+
+impl PostStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::Scheduled => "scheduled",
+            Self::Published => "published",
+        }
+    }
+}
+
+impl Display for PostStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl ToSql<Text, Pg> for PostStatus {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <str as ToSql<Text, Pg>>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Text, Pg> for PostStatus {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Pg>>::from_sql(bytes)?.as_str() {
+            "draft" => Ok(Self::Draft),
+            "scheduled" => Ok(Self::Scheduled),
+            "published" => Ok(Self::Published),
+            other => Err(format!("Unknown post status {:?}", other).into()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
 pub struct NewPost {
     #[validate(custom = "Post::valid_title")]
     pub title: String,
     #[validate(length(max = 140))]
     pub subtitle: String,
     pub body: String,
+    /// New posts are published right away unless stated otherwise, while
+    /// edits keep the status.
+    pub status: Option<PostStatus>,
+    /// When a scheduled post gets published, in UTC.
+    #[serde(rename = "publishAt")]
+    pub publish_at: Option<NaiveDateTime>,
+    /// The tags of the post, which edits keep when left out.
+    #[validate(custom = "Post::valid_tags")]
+    pub tags: Option<Vec<String>>,
+}
+
+impl NewPost {
+    /// Returns the status and publication date the post should get at `now`,
+    /// given the status and publication date of its `current` version when
+    /// editing.
+    ///
+    /// ```
+    /// use chrono::NaiveDate;
+    /// use ephemeris::{NewPost, PostStatus};
+    ///
+    /// let date = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(0, 0, 0);
+    /// let now = date(10).unwrap();
+    /// let post = |status, publish_at| NewPost {
+    ///     title: "Title".into(),
+    ///     subtitle: String::new(),
+    ///     body: String::new(),
+    ///     status,
+    ///     publish_at,
+    ///     tags: None,
+    /// };
+    ///
+    /// // New posts are published right away, unless they're drafts.
+    /// let draft = post(Some(PostStatus::Draft), None).publication(None, now).unwrap();
+    /// assert_eq!(draft, (PostStatus::Draft, None));
+    /// assert_eq!(
+    ///     post(None, None).publication(None, now).unwrap(),
+    ///     (PostStatus::Published, Some(now)),
+    /// );
+    ///
+    /// // Drafts can be scheduled, but only for the future.
+    /// let scheduled = post(Some(PostStatus::Scheduled), date(20))
+    ///     .publication(Some(draft), now)
+    ///     .unwrap();
+    /// assert_eq!(scheduled, (PostStatus::Scheduled, date(20)));
+    /// assert!(post(Some(PostStatus::Scheduled), date(5)).publication(None, now).is_err());
+    /// assert!(post(Some(PostStatus::Scheduled), None).publication(None, now).is_err());
+    ///
+    /// // Publishing sets the publication date, which later edits keep.
+    /// let published = post(Some(PostStatus::Published), None)
+    ///     .publication(Some(scheduled), now)
+    ///     .unwrap();
+    /// assert_eq!(published, (PostStatus::Published, Some(now)));
+    /// let later = date(15).unwrap();
+    /// assert_eq!(post(None, None).publication(Some(published), later).unwrap(), published);
+    /// assert_eq!(
+    ///     post(Some(PostStatus::Published), None).publication(Some(published), later).unwrap(),
+    ///     published,
+    /// );
+    /// ```
+    pub fn publication(
+        &self, current: Option<(PostStatus, Option<NaiveDateTime>)>, now: NaiveDateTime,
+    ) -> Result<(PostStatus, Option<NaiveDateTime>), ApiError> {
+        let status = match (self.status, current) {
+            (Some(status), _) => status,
+            (None, Some(current)) => return Ok(current),
+            (None, None) => PostStatus::Published,
+        };
+
+        Ok(match status {
+            PostStatus::Draft => (status, None),
+            PostStatus::Scheduled => match self.publish_at {
+                Some(publish_at) if publish_at > now => (status, Some(publish_at)),
+                _ => {
+                    return Err(ApiError::new(
+                        400,
+                        "Scheduled posts need a publishAt date in the future.".into(),
+                    ))
+                }
+            },
+            PostStatus::Published => match current {
+                Some((PostStatus::Published, published_at)) => (status, published_at),
+                _ => (status, Some(now)),
+            },
+        })
+    }
 }
 
 #[derive(Queryable, Serialize)]
@@ -27,6 +166,14 @@ pub struct Post {
     pub created_at: NaiveDateTime,
     #[serde(rename = "updatedAt")]
     pub updated_at: Option<NaiveDateTime>,
+    pub status: PostStatus,
+    #[serde(rename = "publishedAt")]
+    pub published_at: Option<NaiveDateTime>,
+    /// Identifies the post among its author's, for use in URLs.
+    pub slug: String,
+    /// The body rendered from Markdown.
+    #[serde(rename = "bodyHtml")]
+    pub body_html: Option<String>,
 }
 
 #[derive(Debug, Insertable)]
@@ -36,6 +183,21 @@ pub struct InsertablePost {
     pub title: String,
     pub subtitle: String,
     pub body: String,
+    pub status: PostStatus,
+    pub published_at: Option<NaiveDateTime>,
+    pub slug: String,
+    pub body_html: String,
+}
+
+#[derive(Debug, AsChangeset)]
+#[diesel(table_name = posts, treat_none_as_null = true)]
+struct PostChanges {
+    title: String,
+    subtitle: String,
+    body: String,
+    body_html: String,
+    status: PostStatus,
+    published_at: Option<NaiveDateTime>,
 }
 
 /// Filters to be applied to a post search.
@@ -46,6 +208,11 @@ pub struct PostFilters {
     #[validate(range(min = 0))]
     pub limit: Option<i64>,
     pub author: Option<String>,
+    /// Comma-separated tags the posts must carry.
+    pub tag: Option<String>,
+    /// Whether the posts need any or all of the tags.
+    #[serde(rename = "match", default)]
+    pub tag_match: TagMatch,
 }
 
 impl Post {
@@ -61,13 +228,34 @@ impl Post {
         Ok(())
     }
 
-    /// Returns all posts matching the filters.
-    pub fn find_all(filters: PostFilters) -> Result<Vec<Self>, ApiError> {
+    /// Returns all posts matching the filters that `viewer` may see, newest
+    /// first. Unpublished posts are only visible to their author, and blocks
+    /// and mutes hide posts as well.
+    pub fn find_all(filters: PostFilters, viewer: Option<&User>) -> Result<Vec<Self>, ApiError> {
         let mut query = match filters.author {
             Some(s) => posts::table.filter(posts::author.eq(s)).into_boxed(),
             None => posts::table.into_boxed(),
+        };
+
+        query = match viewer {
+            Some(viewer) => query
+                .filter(
+                    posts::status
+                        .eq(PostStatus::Published)
+                        .or(posts::author.eq(viewer.username.to_owned())),
+                )
+                .filter(posts::author.ne_all(viewer.hidden_authors())),
+            None => query.filter(posts::status.eq(PostStatus::Published)),
+        };
+
+        if let Some(tags) = filters.tag {
+            query = Self::filter_tags(query, &tags, filters.tag_match);
         }
-        .paginate(filters.offset.unwrap_or(0));
+
+        let mut query = query
+            .paginate(filters.offset.unwrap_or(0))
+            .column("published_at")
+            .direction(Direction::DESC);
 
         if let Some(limit) = filters.limit {
             query = query.limit(limit)
@@ -78,18 +266,114 @@ impl Post {
         Ok(posts)
     }
 
-    /// Finds a post by its id.
-    pub fn find(id: i32) -> Result<Self, ApiError> {
-        Ok(posts::table
+    /// Finds a post by its id, as long as `viewer` may see it.
+    pub fn find(id: i32, viewer: Option<&User>) -> Result<Self, ApiError> {
+        let post: Self = posts::table
             .filter(posts::id.eq(id))
-            .first(&mut db::connection()?)?)
+            .first(&mut db::connection()?)?;
+
+        if !post.readable_by(viewer)? {
+            return Err(ApiError::new(404, "Record not found".into()));
+        }
+        Ok(post)
+    }
+
+    /// Finds a post by its id for `editor` to change. Unlike `Post::find`,
+    /// blocks and mutes don't hide it, so whether moderators may act on posts
+    /// of users who blocked or muted them is left to `User::authorize`.
+    pub fn find_for_edit(id: i32, editor: &User) -> Result<Self, ApiError> {
+        let post: Self = posts::table
+            .filter(posts::id.eq(id))
+            .first(&mut db::connection()?)?;
+
+        if !post.visible_to(Some(editor)) {
+            return Err(ApiError::new(404, "Record not found".into()));
+        }
+        Ok(post)
     }
 
-    /// Updates the post with the supplied new post.
-    pub fn edit(&self, update: NewPost) -> Result<Self, ApiError> {
-        Ok(diesel::update(posts::table.filter(posts::id.eq(self.id)))
-            .set(update)
-            .get_result(&mut db::connection()?)?)
+    /// Whether `viewer` may see the post and its author isn't hidden from them
+    /// because of a block or mute.
+    pub(crate) fn readable_by(&self, viewer: Option<&User>) -> Result<bool, ApiError> {
+        if !self.visible_to(viewer) {
+            return Ok(false);
+        }
+        match viewer {
+            Some(viewer) => Ok(!viewer.hides(&self.author)?),
+            None => Ok(true),
+        }
+    }
+
+    /// Whether `viewer` may see the post.
+    pub fn visible_to(&self, viewer: Option<&User>) -> bool {
+        self.status == PostStatus::Published
+            || viewer.is_some_and(|viewer| viewer.username == self.author)
+    }
+
+    /// Updates the post with the supplied new post, keeping the previous
+    /// version in its history if the content changed.
+    pub fn edit(&self, update: NewPost, editor: &User) -> Result<Self, ApiError> {
+        let (status, published_at) = update.publication(
+            Some((self.status, self.published_at)),
+            Utc::now().naive_utc(),
+        )?;
+        let post = db::connection()?.transaction(|conn| {
+            self.update_slug(conn, &update.title)?;
+            let post: Self = diesel::update(posts::table.filter(posts::id.eq(self.id)))
+                .set(PostChanges {
+                    title: update.title,
+                    subtitle: update.subtitle,
+                    body_html: markdown::render(&update.body),
+                    body: update.body,
+                    status,
+                    published_at,
+                })
+                .get_result(conn)?;
+
+            if (&post.title, &post.subtitle, &post.body)
+                != (&self.title, &self.subtitle, &self.body)
+            {
+                diesel::insert_into(post_revisions::table)
+                    .values(NewPostRevision::of(&post, editor))
+                    .execute(conn)?;
+            }
+            if let Some(tags) = &update.tags {
+                Self::set_tags(conn, post.id, tags)?;
+            }
+            Ok::<_, diesel::result::Error>(post)
+        })?;
+
+        Ok(post)
+    }
+
+    /// Publishes the scheduled posts whose time has come.
+    pub fn publish_scheduled() -> Result<Vec<Self>, ApiError> {
+        let posts: Vec<Self> = diesel::update(posts::table)
+            .filter(posts::status.eq(PostStatus::Scheduled))
+            .filter(posts::published_at.le(Utc::now().naive_utc()))
+            .set(posts::status.eq(PostStatus::Published))
+            .get_results(&mut db::connection()?)?;
+
+        for post in &posts {
+            info!("Published scheduled post {:?} (#{})", post.title, post.id);
+        }
+        Ok(posts)
+    }
+
+    /// Renders the bodies of posts that weren't rendered when written.
+    pub fn render_missing() -> Result<usize, ApiError> {
+        let mut conn = db::connection()?;
+        let posts: Vec<(i32, String)> = posts::table
+            .filter(posts::body_html.is_null())
+            .select((posts::id, posts::body))
+            .load(&mut conn)?;
+
+        for (id, body) in &posts {
+            diesel::update(posts::table.filter(posts::id.eq(id)))
+                .set(posts::body_html.eq(markdown::render(body)))
+                .execute(&mut conn)?;
+        }
+        Ok(posts.len())
     }
 
     /// Deletes the post.
@@ -103,16 +387,30 @@ impl TryFrom<(NewPost, &User)> for Post {
     type Error = ApiError;
 
     fn try_from((post, author): (NewPost, &User)) -> Result<Self, Self::Error> {
-        let post = diesel::insert_into(posts::table)
-            .values(&InsertablePost {
-                author: author.username.to_owned(),
-                title: post.title.trim().into(),
-                subtitle: post.subtitle.trim().into(),
-                body: post.body.trim().into(),
-            })
-            .get_result::<Post>(&mut db::connection()?)?;
-
-        info!("{:?} posted {:?} (#{})", author.username, post.title, post.id);
+        let (status, published_at) = post.publication(None, Utc::now().naive_utc())?;
+        let tags = post.tags;
+        let post = db::connection()?.transaction(|conn| {
+            let title = post.title.trim();
+            let post = diesel::insert_into(posts::table)
+                .values(&InsertablePost {
+                    author: author.username.to_owned(),
+                    title: title.into(),
+                    subtitle: post.subtitle.trim().into(),
+                    body: post.body.trim().into(),
+                    body_html: markdown::render(post.body.trim()),
+                    status,
+                    published_at,
+                    slug: Post::unique_slug(conn, &author.username, title, None)?,
+                })
+                .get_result::<Post>(conn)?;
+            diesel::insert_into(post_revisions::table)
+                .values(NewPostRevision::of(&post, author))
+                .execute(conn)?;
+            Post::set_tags(conn, post.id, tags.as_deref().unwrap_or_default())?;
+            Ok::<_, diesel::result::Error>(post)
+        })?;
+
+        info!("{:?} posted {:?} (#{}, {})", author.username, post.title, post.id, post.status);
 
         Ok(post)
     }
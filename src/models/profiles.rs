@@ -0,0 +1,114 @@
+use crate::{
+    db,
+    schema::{comments, posts},
+    ApiError, PostStatus, User,
+};
+use diesel::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use url::Url;
+use validator::ValidationError;
+
+/// The maximum number of links on a profile.
+const MAX_LINKS: usize = 5;
+
+/// A `User`'s public profile, including some statistics.
+#[derive(Debug, Serialize)]
+pub struct Profile {
+    #[serde(flatten)]
+    pub user: User,
+    #[serde(rename = "postCount")]
+    pub post_count: i64,
+    #[serde(rename = "commentCount")]
+    pub comment_count: i64,
+    #[serde(rename = "followerCount")]
+    pub follower_count: i64,
+    #[serde(rename = "followingCount")]
+    pub following_count: i64,
+}
+
+impl User {
+    /// Returns the `User`'s public profile.
+    pub fn profile(self) -> Result<Profile, ApiError> {
+        let (follower_count, following_count) = self.follow_counts()?;
+        let mut conn = db::connection()?;
+        let post_count = posts::table
+            .filter(posts::author.eq(&self.username))
+            .filter(posts::status.eq(PostStatus::Published))
+            .count()
+            .get_result(&mut conn)?;
+        let comment_count = comments::table
+            .filter(comments::author.eq(&self.username))
+            .count()
+            .get_result(&mut conn)?;
+
+        Ok(Profile {
+            user: self,
+            post_count,
+            comment_count,
+            follower_count,
+            following_count,
+        })
+    }
+
+    /// Checks if the given `&str` constitutes a valid display name.
+    ///
+    /// Unlike usernames, display names can contain any printable characters
+    /// (including spaces), but are limited to 50 of them.
+    ///
+    /// ```
+    /// use ephemeris::User;
+    ///
+    /// assert!(User::valid_display_name("Zoë Ångström 🚀").is_ok());
+    /// assert!(User::valid_display_name("  ").is_err());
+    /// assert!(User::valid_display_name("tab\tseparated").is_err());
+    /// ```
+    pub fn valid_display_name(name: &str) -> Result<(), ValidationError> {
+        match name.trim() {
+            "" => Err(ValidationError::new("Display name can't be empty.")),
+            s if s.chars().count() > 50 => Err(ValidationError::new(
+                "Display name can be at most 50 characters long.",
+            )),
+            s if s.chars().any(char::is_control) => Err(ValidationError::new(
+                "Display name can't contain control characters.",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks if the given `&str` is an absolute `http` or `https` URL, so
+    /// it's safe to link to.
+    ///
+    /// ```
+    /// use ephemeris::User;
+    ///
+    /// assert!(User::valid_link("https://example.com/~jane").is_ok());
+    /// assert!(User::valid_link("javascript:alert(1)").is_err());
+    /// assert!(User::valid_link("example.com").is_err());
+    /// ```
+    pub fn valid_link(link: &str) -> Result<(), ValidationError> {
+        match Url::parse(link) {
+            Ok(url) if link.len() <= 200 && matches!(url.scheme(), "http" | "https") => Ok(()),
+            _ => Err(ValidationError::new(
+                "Links must be http or https URLs of at most 200 characters.",
+            )),
+        }
+    }
+
+    /// Checks if the given links can be shown on a profile.
+    pub fn valid_links(links: &[String]) -> Result<(), ValidationError> {
+        if links.len() > MAX_LINKS {
+            return Err(ValidationError::new("A profile can have at most 5 links."));
+        }
+        links.iter().try_for_each(|link| Self::valid_link(link))
+    }
+}
+
+/// Deserializes a field that can be left out to keep its value or set to
+/// `null` to clear it.
+pub(crate) fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
@@ -0,0 +1,225 @@
+use crate::{
+    db,
+    diff::{self, DiffLine},
+    schema::{comment_revisions, post_revisions},
+    ApiError, Comment, NewPost, Post, UpdateComment, User,
+};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// The revisions to compare. Without `to`, `from` is compared to the latest
+/// revision.
+#[derive(Debug, Deserialize)]
+pub struct RevisionDiffQuery {
+    pub from: i32,
+    pub to: Option<i32>,
+}
+
+/// A version of a `Post`, saved whenever it's created or edited.
+#[derive(Debug, Queryable, Serialize)]
+pub struct PostRevision {
+    pub id: i32,
+    pub post: i32,
+    /// The user who created this version, if they still exist.
+    #[serde(skip_serializing)]
+    pub editor: Option<Uuid>,
+    pub title: String,
+    pub subtitle: String,
+    pub body: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = post_revisions)]
+pub(crate) struct NewPostRevision<'a> {
+    pub post: i32,
+    pub editor: Uuid,
+    pub title: &'a str,
+    pub subtitle: &'a str,
+    pub body: &'a str,
+}
+
+impl<'a> NewPostRevision<'a> {
+    /// Records the current version of `post`, as saved by `editor`.
+    pub fn of(post: &'a Post, editor: &User) -> Self {
+        Self {
+            post: post.id,
+            editor: editor.id,
+            title: &post.title,
+            subtitle: &post.subtitle,
+            body: &post.body,
+        }
+    }
+}
+
+/// The changes between two `PostRevision`s.
+#[derive(Debug, Serialize)]
+pub struct PostRevisionDiff {
+    pub from: i32,
+    pub to: i32,
+    pub title: Vec<DiffLine>,
+    pub subtitle: Vec<DiffLine>,
+    pub body: Vec<DiffLine>,
+}
+
+/// A version of a `Comment`, saved whenever it's created or edited.
+#[derive(Debug, Queryable, Serialize)]
+pub struct CommentRevision {
+    pub id: i32,
+    pub comment: i32,
+    #[serde(skip_serializing)]
+    pub editor: Option<Uuid>,
+    pub message: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = comment_revisions)]
+pub(crate) struct NewCommentRevision<'a> {
+    pub comment: i32,
+    pub editor: Uuid,
+    pub message: &'a str,
+}
+
+impl<'a> NewCommentRevision<'a> {
+    /// Records the current version of `comment`, as saved by `editor`.
+    pub fn of(comment: &'a Comment, editor: &User) -> Self {
+        Self {
+            comment: comment.id,
+            editor: editor.id,
+            message: &comment.message,
+        }
+    }
+}
+
+/// The changes between two `CommentRevision`s.
+#[derive(Debug, Serialize)]
+pub struct CommentRevisionDiff {
+    pub from: i32,
+    pub to: i32,
+    pub message: Vec<DiffLine>,
+}
+
+impl Post {
+    /// Returns all versions of the post, newest first.
+    pub fn revisions(&self) -> Result<Vec<PostRevision>, ApiError> {
+        Ok(post_revisions::table
+            .filter(post_revisions::post.eq(self.id))
+            .order(post_revisions::id.desc())
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Finds a version of the post by its id, or the latest one.
+    pub fn revision(&self, id: Option<i32>) -> Result<PostRevision, ApiError> {
+        let mut query = post_revisions::table
+            .filter(post_revisions::post.eq(self.id))
+            .order(post_revisions::id.desc())
+            .into_boxed();
+        if let Some(id) = id {
+            query = query.filter(post_revisions::id.eq(id));
+        }
+
+        Ok(query.first(&mut db::connection()?)?)
+    }
+
+    /// Compares two versions of the post.
+    pub fn diff(&self, query: RevisionDiffQuery) -> Result<PostRevisionDiff, ApiError> {
+        let from = self.revision(Some(query.from))?;
+        let to = self.revision(query.to)?;
+
+        Ok(PostRevisionDiff {
+            from: from.id,
+            to: to.id,
+            title: diff::lines(&from.title, &to.title),
+            subtitle: diff::lines(&from.subtitle, &to.subtitle),
+            body: diff::lines(&from.body, &to.body),
+        })
+    }
+
+    /// Makes an earlier version of the post the current one by editing it,
+    /// so the restore itself shows up in the history.
+    pub fn restore(&self, revision: i32, editor: &User) -> Result<Self, ApiError> {
+        let revision = self.revision(Some(revision))?;
+        self.edit(
+            NewPost {
+                title: revision.title,
+                subtitle: revision.subtitle,
+                body: revision.body,
+                status: None,
+                publish_at: None,
+                tags: None,
+            },
+            editor,
+        )
+    }
+}
+
+impl Comment {
+    /// Returns all versions of the comment, newest first.
+    pub fn revisions(&self) -> Result<Vec<CommentRevision>, ApiError> {
+        Ok(comment_revisions::table
+            .filter(comment_revisions::comment.eq(self.id))
+            .order(comment_revisions::id.desc())
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Finds a version of the comment by its id, or the latest one.
+    pub fn revision(&self, id: Option<i32>) -> Result<CommentRevision, ApiError> {
+        let mut query = comment_revisions::table
+            .filter(comment_revisions::comment.eq(self.id))
+            .order(comment_revisions::id.desc())
+            .into_boxed();
+        if let Some(id) = id {
+            query = query.filter(comment_revisions::id.eq(id));
+        }
+
+        Ok(query.first(&mut db::connection()?)?)
+    }
+
+    /// Compares two versions of the comment.
+    pub fn diff(&self, query: RevisionDiffQuery) -> Result<CommentRevisionDiff, ApiError> {
+        let from = self.revision(Some(query.from))?;
+        let to = self.revision(query.to)?;
+
+        Ok(CommentRevisionDiff {
+            from: from.id,
+            to: to.id,
+            message: diff::lines(&from.message, &to.message),
+        })
+    }
+
+    /// Makes an earlier version of the comment the current one by editing
+    /// it, so the restore itself shows up in the history.
+    pub fn restore(&self, revision: i32, editor: &User) -> Result<Self, ApiError> {
+        let revision = self.revision(Some(revision))?;
+        self.edit(
+            UpdateComment {
+                id: self.id,
+                message: revision.message,
+            },
+            editor,
+        )
+    }
+}
+
+impl User {
+    /// Returns the post versions the `User` saved, newest first.
+    pub fn post_revisions(&self) -> Result<Vec<PostRevision>, ApiError> {
+        Ok(post_revisions::table
+            .filter(post_revisions::editor.eq(self.id))
+            .order(post_revisions::id.desc())
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Returns the comment versions the `User` saved, newest first.
+    pub fn comment_revisions(&self) -> Result<Vec<CommentRevision>, ApiError> {
+        Ok(comment_revisions::table
+            .filter(comment_revisions::editor.eq(self.id))
+            .order(comment_revisions::id.desc())
+            .load(&mut db::connection()?)?)
+    }
+}
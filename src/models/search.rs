@@ -0,0 +1,255 @@
+use crate::{
+    config,
+    db::{self, Paginate},
+    ApiError, User,
+};
+use chrono::NaiveDateTime;
+use diesel::{
+    prelude::*,
+    sql_query,
+    sql_types::{Array, Float, Int4, Text, Timestamp},
+};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::Validate;
+
+/// Marks the start and end of a match in snippets, before they're escaped.
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+lazy_static! {
+    /// The text search configuration posts and comments are indexed and
+    /// searched with, e.g. `english` or `simple`. It's part of the queries
+    /// rather than bound, so it's restricted to what names can consist of.
+    static ref LANGUAGE: String = {
+        let language: String = config::var_or("SEARCH_LANGUAGE", "english".to_string());
+        if language.is_empty() || !language.chars().all(|c| c.is_ascii_lowercase() || c == '_') {
+            panic!("SEARCH_LANGUAGE must be a text search configuration, got {:?}", language);
+        }
+        language
+    };
+
+    /// Ranks published posts and the comments on them by how well their text
+    /// matches the query. Snippets are only made for the page of results
+    /// returned, using `HEADLINES`.
+    static ref SEARCH: String = format!(
+        r#"
+        SELECT 'post' AS "kind", p."id", p."id" AS "post", p."author", p."title", p."slug",
+            ts_rank({post}, q) AS "rank", p."created_at"
+        FROM "posts" p, websearch_to_tsquery('{language}', $1) q
+        WHERE {post} @@ q AND p."status" = 'published' AND p."author" <> ALL($2)
+        UNION ALL
+        SELECT 'comment', c."id", p."id", c."author", p."title", p."slug",
+            ts_rank({comment}, q), c."created_at"
+        FROM "comments" c JOIN "posts" p ON p."id" = c."post",
+            websearch_to_tsquery('{language}', $1) q
+        WHERE {comment} @@ q AND p."status" = 'published'
+            AND c."author" <> ALL($2) AND p."author" <> ALL($2)
+        "#,
+        language = *LANGUAGE,
+        post = post_vector("p."),
+        comment = comment_vector("c."),
+    );
+
+    /// Highlights the matches in the given posts and comments.
+    static ref HEADLINES: String = format!(
+        r#"
+        SELECT 'post' AS "kind", p."id", ts_headline(
+            '{language}',
+            concat_ws(' ', p."title", NULLIF(p."subtitle", ''), p."body"),
+            q,
+            $2
+        ) AS "snippet"
+        FROM "posts" p, websearch_to_tsquery('{language}', $1) q
+        WHERE p."id" = ANY($3)
+        UNION ALL
+        SELECT 'comment', c."id", ts_headline('{language}', c."message", q, $2)
+        FROM "comments" c, websearch_to_tsquery('{language}', $1) q
+        WHERE c."id" = ANY($4)
+        "#,
+        language = *LANGUAGE,
+    );
+}
+
+/// The text search vector of posts, with `prefix` qualifying the columns. It
+/// has to match the expression the search index is created with to use it.
+fn post_vector(prefix: &str) -> String {
+    format!(
+        r#"(setweight(to_tsvector('{language}', {p}"title"), 'A')
+        || setweight(to_tsvector('{language}', {p}"subtitle"), 'B')
+        || setweight(to_tsvector('{language}', {p}"body"), 'C'))"#,
+        language = *LANGUAGE,
+        p = prefix,
+    )
+}
+
+/// The text search vector of comments, like `post_vector`.
+fn comment_vector(prefix: &str) -> String {
+    format!(r#"to_tsvector('{}', {}"message")"#, *LANGUAGE, prefix)
+}
+
+/// A search through posts and comments.
+#[derive(Debug, Deserialize, Validate)]
+pub struct SearchQuery {
+    /// The words to search for. Quoted phrases, `or` and `-` to exclude words
+    /// are supported.
+    #[validate(length(min = 1, max = 200))]
+    pub q: String,
+    #[validate(range(min = 0))]
+    pub offset: Option<i64>,
+    #[validate(range(min = 0))]
+    pub limit: Option<i64>,
+}
+
+/// A post or comment matching a search.
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    /// Either `post` or `comment`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub id: i32,
+    /// The post, or the one the comment is on.
+    pub post: i32,
+    pub author: String,
+    /// The title of the post.
+    pub title: String,
+    /// The slug of the post.
+    pub slug: String,
+    /// An HTML excerpt with the matches in `<mark>` elements.
+    pub snippet: String,
+    pub rank: f32,
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(QueryableByName)]
+struct Match {
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Int4)]
+    id: i32,
+    #[diesel(sql_type = Int4)]
+    post: i32,
+    #[diesel(sql_type = Text)]
+    author: String,
+    #[diesel(sql_type = Text)]
+    title: String,
+    #[diesel(sql_type = Text)]
+    slug: String,
+    #[diesel(sql_type = Float)]
+    rank: f32,
+    #[diesel(sql_type = Timestamp)]
+    created_at: NaiveDateTime,
+}
+
+#[derive(QueryableByName)]
+struct Headline {
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Int4)]
+    id: i32,
+    #[diesel(sql_type = Text)]
+    snippet: String,
+}
+
+impl SearchResult {
+    /// Creates the search indexes for `SEARCH_LANGUAGE` unless they exist.
+    /// The ones for English, the default, are created by the migrations.
+    pub fn create_indexes() -> Result<(), ApiError> {
+        let name = |table: &str| match LANGUAGE.as_str() {
+            "english" => format!("{}_search_idx", table),
+            language => format!("{}_search_{}_idx", table, language),
+        };
+        let mut conn = db::connection()?;
+        sql_query(format!(
+            r#"CREATE INDEX IF NOT EXISTS "{}" ON "posts" USING GIN ({})"#,
+            name("posts"),
+            post_vector(""),
+        ))
+        .execute(&mut conn)?;
+        sql_query(format!(
+            r#"CREATE INDEX IF NOT EXISTS "{}" ON "comments" USING GIN (({}))"#,
+            name("comments"),
+            comment_vector(""),
+        ))
+        .execute(&mut conn)?;
+        Ok(())
+    }
+
+    /// Searches published posts and their comments, best matches first,
+    /// leaving out the ones `viewer` shouldn't see because of blocks or
+    /// mutes.
+    pub fn search(query: SearchQuery, viewer: Option<&User>) -> Result<Vec<Self>, ApiError> {
+        let mut conn = db::connection()?;
+        let q = query.q.trim();
+        let hidden: Vec<String> = match viewer {
+            Some(viewer) => viewer.hidden_authors().load(&mut conn)?,
+            None => Vec::new(),
+        };
+
+        let mut search = sql_query(SEARCH.as_str())
+            .bind::<Text, _>(q)
+            .bind::<Array<Text>, _>(hidden)
+            .paginate(query.offset.unwrap_or(0))
+            .column("rank");
+
+        if let Some(limit) = query.limit {
+            search = search.limit(limit)
+        }
+
+        let matches: Vec<Match> = search.load(&mut conn)?;
+        let ids = |kind: &str| -> Vec<i32> {
+            matches.iter().filter(|m| m.kind == kind).map(|m| m.id).collect()
+        };
+        let options = format!(
+            "StartSel={}, StopSel={}, MaxWords=35, MinWords=15, MaxFragments=2",
+            MATCH_START, MATCH_END,
+        );
+        let mut headlines: HashMap<(String, i32), String> = sql_query(HEADLINES.as_str())
+            .bind::<Text, _>(q)
+            .bind::<Text, _>(options)
+            .bind::<Array<Int4>, _>(ids("post"))
+            .bind::<Array<Int4>, _>(ids("comment"))
+            .load::<Headline>(&mut conn)?
+            .into_iter()
+            .map(|headline| ((headline.kind, headline.id), headline.snippet))
+            .collect();
+
+        Ok(matches
+            .into_iter()
+            .map(|m| SearchResult {
+                snippet: highlight(
+                    &headlines.remove(&(m.kind.clone(), m.id)).unwrap_or_default(),
+                ),
+                kind: m.kind,
+                id: m.id,
+                post: m.post,
+                author: m.author,
+                title: m.title,
+                slug: m.slug,
+                rank: m.rank,
+                created_at: m.created_at,
+            })
+            .collect())
+    }
+}
+
+/// Escapes a snippet for use in HTML, turning the marked matches into `<mark>`
+/// elements.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
@@ -0,0 +1,157 @@
+use crate::{
+    db,
+    schema::{old_post_slugs, posts},
+    ApiError, Post, User,
+};
+use deunicode::deunicode;
+use diesel::prelude::*;
+
+/// The maximum length of a slug, leaving room for a suffix.
+pub(crate) const MAX_SLUG_LENGTH: usize = 80;
+
+/// Where a slug leads.
+pub enum SlugTarget {
+    /// The post currently using the slug.
+    Post(Post),
+    /// The current slug of a post that used to have the requested one.
+    Moved(String),
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = old_post_slugs)]
+struct OldPostSlug<'a> {
+    post: i32,
+    slug: &'a str,
+}
+
+impl Post {
+    /// Turns a title into the URL-friendly part of a post's address, made of
+    /// lowercase ASCII letters, digits and dashes.
+    ///
+    /// ```
+    /// use ephemeris::Post;
+    ///
+    /// assert_eq!(Post::slugify("Hello, World!"), "hello-world");
+    /// assert_eq!(Post::slugify("Crème brûlée à Zürich"), "creme-brulee-a-zurich");
+    /// assert_eq!(Post::slugify("東京"), "dong-jing");
+    /// assert_eq!(Post::slugify("?!"), "post");
+    /// ```
+    pub fn slugify(title: &str) -> String {
+        let mut slug = String::new();
+        for c in deunicode(title).chars() {
+            if c.is_ascii_alphanumeric() {
+                slug.push(c.to_ascii_lowercase());
+            } else if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        }
+
+        if slug.len() > MAX_SLUG_LENGTH {
+            // Cut at a word boundary where there is one.
+            slug.truncate(MAX_SLUG_LENGTH);
+            if let Some(end) = slug.rfind('-') {
+                slug.truncate(end);
+            }
+        }
+
+        match slug.trim_end_matches('-') {
+            "" => "post".into(),
+            slug => slug.into(),
+        }
+    }
+
+    /// Returns a slug for `title` that none of `author`'s other posts use or
+    /// used, numbering it if needed. `except` is the post being edited, which
+    /// may reclaim its own slugs.
+    pub(crate) fn unique_slug(
+        conn: &mut PgConnection, author: &str, title: &str, except: Option<i32>,
+    ) -> Result<String, diesel::result::Error> {
+        let base = Self::slugify(title);
+        let pattern = format!("{}%", base);
+
+        let mut taken: Vec<(i32, String)> = posts::table
+            .filter(posts::author.eq(author))
+            .filter(posts::slug.like(&pattern))
+            .select((posts::id, posts::slug))
+            .load(conn)?;
+        taken.extend(
+            old_post_slugs::table
+                .inner_join(posts::table)
+                .filter(posts::author.eq(author))
+                .filter(old_post_slugs::slug.like(&pattern))
+                .select((posts::id, old_post_slugs::slug))
+                .load::<(i32, String)>(conn)?,
+        );
+        taken.retain(|(id, _)| Some(*id) != except);
+
+        Ok((1..)
+            .map(|n| if n == 1 { base.clone() } else { format!("{}-{}", base, n) })
+            .find(|slug| taken.iter().all(|(_, taken)| taken != slug))
+            .expect("There's always a free slug"))
+    }
+
+    /// Gives the post a new slug if `title` changed, keeping the current one
+    /// as a redirect.
+    pub(crate) fn update_slug(
+        &self, conn: &mut PgConnection, title: &str,
+    ) -> Result<(), diesel::result::Error> {
+        if title == self.title {
+            return Ok(());
+        }
+        let slug = Self::unique_slug(conn, &self.author, title, Some(self.id))?;
+        if slug == self.slug {
+            return Ok(());
+        }
+
+        diesel::insert_into(old_post_slugs::table)
+            .values(OldPostSlug {
+                post: self.id,
+                slug: &self.slug,
+            })
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        // The new slug might have been an old one of this post.
+        diesel::delete(old_post_slugs::table)
+            .filter(old_post_slugs::post.eq(self.id))
+            .filter(old_post_slugs::slug.eq(&slug))
+            .execute(conn)?;
+        diesel::update(posts::table.filter(posts::id.eq(self.id)))
+            .set(posts::slug.eq(slug))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Finds the post of `author` with the given slug, or where a post that
+    /// used to have it moved to, as long as `viewer` may see it.
+    pub fn by_slug(
+        author: &str, slug: &str, viewer: Option<&User>,
+    ) -> Result<SlugTarget, ApiError> {
+        let mut conn = db::connection()?;
+        let current: Option<Self> = posts::table
+            .filter(posts::author.eq(author))
+            .filter(posts::slug.eq(slug))
+            .first(&mut conn)
+            .optional()?;
+        let target = match current {
+            Some(post) => Some((post, false)),
+            None => old_post_slugs::table
+                .inner_join(posts::table)
+                .filter(posts::author.eq(author))
+                .filter(old_post_slugs::slug.eq(slug))
+                .order(old_post_slugs::created_at.desc())
+                .select(posts::all_columns)
+                .first::<Self>(&mut conn)
+                .optional()?
+                .map(|post| (post, true)),
+        };
+
+        match target {
+            Some((post, moved)) if post.readable_by(viewer)? => Ok(if moved {
+                SlugTarget::Moved(post.slug)
+            } else {
+                SlugTarget::Post(post)
+            }),
+            _ => Err(ApiError::new(404, "Record not found".into())),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{
+    db::{self, Pagination},
+    schema::{post_tags, posts},
+    ApiError, Post, PostStatus,
+};
+use diesel::{dsl::count_star, pg::Pg, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use validator::ValidationError;
+
+/// The maximum number of tags on a post.
+const MAX_TAGS: usize = 10;
+
+/// Whether posts filtered by several tags need any or all of them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+/// A tag and the number of published posts carrying it.
+#[derive(Debug, Queryable, Serialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+/// A `Post` along with its tags.
+#[derive(Serialize)]
+pub struct TaggedPost {
+    #[serde(flatten)]
+    pub post: Post,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = post_tags)]
+struct NewPostTag<'a> {
+    post: i32,
+    tag: &'a str,
+}
+
+impl Post {
+    /// Normalizes a tag to lowercase, without a leading `#` and with dashes
+    /// instead of whitespace, checking it only contains letters, digits and
+    /// dashes and is at most 30 characters long.
+    ///
+    /// ```
+    /// use ephemeris::Post;
+    ///
+    /// assert_eq!(Post::normalize_tag(" #Rust ").unwrap(), "rust");
+    /// assert_eq!(Post::normalize_tag("Web  Development").unwrap(), "web-development");
+    /// assert_eq!(Post::normalize_tag("Café").unwrap(), "café");
+    /// assert!(Post::normalize_tag("#").is_err());
+    /// assert!(Post::normalize_tag("c++").is_err());
+    /// ```
+    pub fn normalize_tag(tag: &str) -> Result<String, ValidationError> {
+        let tag = tag
+            .trim()
+            .trim_start_matches('#')
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join("-")
+            .to_lowercase();
+
+        if tag.is_empty() {
+            return Err(ValidationError::new("Tags can't be empty."));
+        }
+        if tag.chars().count() > 30 {
+            return Err(ValidationError::new("Tags can be at most 30 characters long."));
+        }
+        if !tag.chars().all(|c| c.is_alphanumeric() || c == '-') {
+            return Err(ValidationError::new(
+                "Tags can only contain letters, digits and dashes.",
+            ));
+        }
+        Ok(tag)
+    }
+
+    /// Checks if the given tags can be attached to a post.
+    pub fn valid_tags(tags: &[String]) -> Result<(), ValidationError> {
+        if tags.len() > MAX_TAGS {
+            return Err(ValidationError::new("A post can have at most 10 tags."));
+        }
+        tags.iter().try_for_each(|tag| Self::normalize_tag(tag).map(|_| ()))
+    }
+
+    /// Normalizes the tags, dropping invalid ones and duplicates.
+    pub(crate) fn normalize_tags(tags: &[String]) -> Vec<String> {
+        let mut normalized: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags.iter().filter_map(|tag| Self::normalize_tag(tag).ok()) {
+            if !normalized.contains(&tag) {
+                normalized.push(tag);
+            }
+        }
+        normalized
+    }
+
+    /// Replaces the tags of the post with the given id.
+    pub(crate) fn set_tags(
+        conn: &mut PgConnection, id: i32, tags: &[String],
+    ) -> Result<(), diesel::result::Error> {
+        diesel::delete(post_tags::table.filter(post_tags::post.eq(id))).execute(conn)?;
+        diesel::insert_into(post_tags::table)
+            .values(
+                Self::normalize_tags(tags)
+                    .iter()
+                    .map(|tag| NewPostTag { post: id, tag })
+                    .collect::<Vec<_>>(),
+            )
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Returns the tags of the post, alphabetically.
+    pub fn tags(&self) -> Result<Vec<String>, ApiError> {
+        Ok(post_tags::table
+            .filter(post_tags::post.eq(self.id))
+            .select(post_tags::tag)
+            .order(post_tags::tag)
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Attaches its tags to the post.
+    pub fn with_tags(self) -> Result<TaggedPost, ApiError> {
+        Ok(TaggedPost {
+            tags: self.tags()?,
+            post: self,
+        })
+    }
+
+    /// Attaches their tags to the posts, loading all of them at once.
+    pub fn tagged(posts: Vec<Self>) -> Result<Vec<TaggedPost>, ApiError> {
+        let rows: Vec<(i32, String)> = post_tags::table
+            .filter(post_tags::post.eq_any(posts.iter().map(|post| post.id)))
+            .order(post_tags::tag)
+            .load(&mut db::connection()?)?;
+
+        let mut tags: HashMap<i32, Vec<String>> = HashMap::new();
+        for (post, tag) in rows {
+            tags.entry(post).or_default().push(tag);
+        }
+
+        Ok(posts
+            .into_iter()
+            .map(|post| TaggedPost {
+                tags: tags.remove(&post.id).unwrap_or_default(),
+                post,
+            })
+            .collect())
+    }
+
+    /// Restricts `query` to the posts carrying any or all of the
+    /// comma-separated `tags`.
+    pub(crate) fn filter_tags(
+        mut query: posts::BoxedQuery<'static, Pg>, tags: &str, tag_match: TagMatch,
+    ) -> posts::BoxedQuery<'static, Pg> {
+        let tags: Vec<String> = tags
+            .split(',')
+            .map(|tag| Self::normalize_tag(tag).unwrap_or_else(|_| tag.trim().to_lowercase()))
+            .collect();
+
+        match tag_match {
+            TagMatch::Any => query.filter(
+                posts::id.eq_any(
+                    post_tags::table
+                        .filter(post_tags::tag.eq_any(tags))
+                        .select(post_tags::post),
+                ),
+            ),
+            TagMatch::All => {
+                for tag in tags {
+                    query = query.filter(
+                        posts::id.eq_any(
+                            post_tags::table
+                                .filter(post_tags::tag.eq(tag))
+                                .select(post_tags::post),
+                        ),
+                    );
+                }
+                query
+            }
+        }
+    }
+
+    /// Returns the tags of published posts with the number of posts carrying
+    /// them, most used first.
+    pub fn tag_counts(page: Pagination) -> Result<Vec<TagCount>, ApiError> {
+        let mut query = post_tags::table
+            .inner_join(posts::table)
+            .filter(posts::status.eq(PostStatus::Published))
+            .group_by(post_tags::tag)
+            .select((post_tags::tag, count_star()))
+            .order((count_star().desc(), post_tags::tag))
+            .offset(page.offset.unwrap_or(0))
+            .into_boxed();
+
+        if let Some(limit) = page.limit {
+            query = query.limit(limit);
+        }
+
+        Ok(query.load(&mut db::connection()?)?)
+    }
+}
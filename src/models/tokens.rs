@@ -0,0 +1,247 @@
+use crate::{config, db, schema::tokens, ApiError};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+use rand::Rng;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+lazy_static! {
+    /// How long a session stays valid without being used.
+    static ref SESSION_IDLE_LIFETIME: Duration =
+        Duration::days(config::var_or("SESSION_IDLE_DAYS", 14));
+    /// How long a session stays valid at most, no matter how often it's used.
+    static ref SESSION_ABSOLUTE_LIFETIME: Duration =
+        Duration::days(config::var_or("SESSION_ABSOLUTE_DAYS", 90));
+}
+
+/// The minimum time between two extensions of a token's expiration, so that
+/// not every request results in a write.
+const TOKEN_EXTENSION_INTERVAL_MINUTES: i64 = 60;
+
+/// How long after logging in through an identity provider again a session
+/// counts as recently reauthenticated.
+const REAUTHENTICATION_MINUTES: i64 = 10;
+
+/// The maximum length of a stored user agent.
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
+/// Information about the client a session is created from.
+#[derive(Debug, Default)]
+pub struct ClientInfo {
+    /// The `User-Agent` header sent by the client.
+    pub user_agent: Option<String>,
+    /// The IP address of the client.
+    pub ip_address: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = tokens)]
+pub struct NewToken {
+    pub user: Uuid,
+    pub expiration: NaiveDateTime,
+    pub hash: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl NewToken {
+    pub fn new(user: Uuid, secret: &str, client: ClientInfo) -> Result<Self, ApiError> {
+        Ok(NewToken {
+            user,
+            hash: Token::hash(secret),
+            expiration: Token::expiration_for(Utc::now().naive_utc())?,
+            user_agent: client
+                .user_agent
+                .map(|s| s.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+            ip_address: client.ip_address,
+        })
+    }
+}
+
+/// A token is a secret used to authenticate a user and is created when the user
+/// logs in.
+///
+/// Only a digest of the secret is stored, so the secret itself is only known
+/// when the token is issued (see [`IssuedToken`]).
+#[derive(Debug, Queryable, Serialize)]
+pub struct Token {
+    /// The id of the token. Unlike the secret, it can be safely exposed.
+    pub id: Uuid,
+    /// The id of the user the token belongs to.
+    #[serde(skip_serializing)]
+    pub user: Uuid,
+    /// The expiration date of the token.
+    pub expiration: NaiveDateTime,
+    /// The date the token was created.
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+    /// The date the token was last updated.
+    #[serde(rename = "updatedAt")]
+    pub updated_at: Option<NaiveDateTime>,
+    /// The SHA-256 digest of the token's secret.
+    #[serde(skip_serializing)]
+    pub hash: String,
+    /// The user agent of the client that logged in.
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    /// The IP address of the client that logged in.
+    #[serde(rename = "ipAddress")]
+    pub ip_address: Option<String>,
+    /// The date the token was last used, accurate to
+    /// `TOKEN_EXTENSION_INTERVAL_MINUTES`.
+    #[serde(rename = "lastUsedAt")]
+    pub last_used_at: NaiveDateTime,
+    /// The date the user last logged in through an identity provider again
+    /// during the session.
+    #[serde(skip_serializing)]
+    pub reauthenticated_at: Option<NaiveDateTime>,
+}
+
+impl Token {
+    /// Generates a new random secret.
+    pub fn generate_secret() -> String {
+        let secret: [u8; 32] = rand::thread_rng().gen();
+        hex(&secret)
+    }
+
+    /// Returns the hex encoded SHA-256 digest of a secret.
+    ///
+    /// ```
+    /// use ephemeris::Token;
+    ///
+    /// assert_eq!(
+    ///     Token::hash("secret"),
+    ///     "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b",
+    /// );
+    /// ```
+    pub fn hash(secret: &str) -> String {
+        hex(&Sha256::digest(secret.as_bytes()))
+    }
+
+    /// Computes the expiration date of a token created at `created_at` if it
+    /// were used now.
+    ///
+    /// The token expires after it hasn't been used for the idle lifetime, or
+    /// after the absolute lifetime has passed since its creation, whichever
+    /// comes first.
+    fn expiration_for(created_at: NaiveDateTime) -> Result<NaiveDateTime, ApiError> {
+        let error = || ApiError::new(500, "Couldn't compute expiration date of token".into());
+        let idle = Utc::now()
+            .naive_utc()
+            .checked_add_signed(*SESSION_IDLE_LIFETIME)
+            .ok_or_else(error)?;
+        let absolute = created_at
+            .checked_add_signed(*SESSION_ABSOLUTE_LIFETIME)
+            .ok_or_else(error)?;
+
+        Ok(idle.min(absolute))
+    }
+
+    /// Finds a `Token` by its secret and extends its expiration if it hasn't
+    /// been extended recently.
+    pub fn find(secret: &str) -> Result<Self, ApiError> {
+        let token = tokens::table
+            .filter(tokens::hash.eq(Self::hash(secret)))
+            .first::<Token>(&mut db::connection()?)?;
+
+        if token.expiration < Utc::now().naive_utc() {
+            diesel::delete(tokens::table.filter(tokens::id.eq(token.id)))
+                .execute(&mut db::connection()?)?;
+            Err(ApiError::new(404, "Token has expired.".into()))
+        } else {
+            token.extend()
+        }
+    }
+
+    /// Finds a `Token` by its `id`.
+    pub fn by_id(id: Uuid) -> Result<Self, ApiError> {
+        Ok(tokens::table
+            .filter(tokens::id.eq(id))
+            .first(&mut db::connection()?)?)
+    }
+
+    /// Extends the expiration of the `Token`, unless it was already extended
+    /// within the last `TOKEN_EXTENSION_INTERVAL_MINUTES`.
+    fn extend(self) -> Result<Self, ApiError> {
+        let now = Utc::now().naive_utc();
+        if now - self.last_used_at < Duration::minutes(TOKEN_EXTENSION_INTERVAL_MINUTES) {
+            return Ok(self);
+        }
+
+        Ok(diesel::update(tokens::table.filter(tokens::id.eq(self.id)))
+            .set((
+                tokens::expiration.eq(Self::expiration_for(self.created_at)?),
+                tokens::last_used_at.eq(now),
+            ))
+            .get_result(&mut db::connection()?)?)
+    }
+
+    /// Replaces the secret of the `Token` with a new one and extends its
+    /// expiration. The absolute lifetime of the session is left untouched.
+    pub fn rotate(&self) -> Result<IssuedToken, ApiError> {
+        let secret = Self::generate_secret();
+        let token = diesel::update(tokens::table.filter(tokens::id.eq(self.id)))
+            .set((
+                tokens::hash.eq(Self::hash(&secret)),
+                tokens::expiration.eq(Self::expiration_for(self.created_at)?),
+                tokens::last_used_at.eq(Utc::now().naive_utc()),
+            ))
+            .get_result(&mut db::connection()?)?;
+
+        Ok(IssuedToken {
+            token: secret,
+            details: token,
+        })
+    }
+
+    /// Records that the user just logged in again during the session.
+    pub fn reauthenticate(&self) -> Result<(), ApiError> {
+        diesel::update(tokens::table.filter(tokens::id.eq(self.id)))
+            .set(tokens::reauthenticated_at.eq(Utc::now().naive_utc()))
+            .execute(&mut db::connection()?)?;
+        Ok(())
+    }
+
+    /// Whether the user logged in again during the session within the last
+    /// `REAUTHENTICATION_MINUTES`.
+    pub fn recently_reauthenticated(&self) -> bool {
+        self.reauthenticated_at.is_some_and(|at| {
+            Utc::now().naive_utc() - at < Duration::minutes(REAUTHENTICATION_MINUTES)
+        })
+    }
+
+    /// Deletes a `Token`.
+    pub fn delete(&self) -> Result<Self, ApiError> {
+        Ok(diesel::delete(tokens::table)
+            .filter(tokens::id.eq(self.id))
+            .get_result(&mut db::connection()?)?)
+    }
+}
+
+impl TryFrom<NewToken> for Token {
+    type Error = ApiError;
+
+    fn try_from(token: NewToken) -> Result<Self, Self::Error> {
+        let token = diesel::insert_into(tokens::table)
+            .values(token)
+            .get_result(&mut db::connection()?)?;
+        Ok(token)
+    }
+}
+
+/// A newly issued `Token` together with its secret, which is returned to the
+/// user only this once.
+#[derive(Debug, Serialize)]
+pub struct IssuedToken {
+    /// The secret used to authenticate as the user.
+    pub token: String,
+    #[serde(flatten)]
+    pub details: Token,
+}
+
+/// Encodes bytes as a lowercase hex string.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
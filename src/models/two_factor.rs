@@ -0,0 +1,295 @@
+use crate::{
+    config, db,
+    schema::{login_challenges, recovery_codes, users},
+    totp, ApiError, Token, User,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+lazy_static! {
+    /// The issuer shown in authenticator apps.
+    static ref TOTP_ISSUER: String = config::var_or("TOTP_ISSUER", "ephemeris".to_string());
+}
+
+/// The number of recovery codes generated when enabling two-factor
+/// authentication.
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// How long a user has to enter their second factor after entering their
+/// password.
+const LOGIN_CHALLENGE_MINUTES: i64 = 5;
+
+/// A TOTP or recovery code entered by the user.
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCode {
+    pub code: String,
+}
+
+/// The second step of logging in with two-factor authentication enabled.
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLogin {
+    /// The secret of the `LoginChallenge` returned by the first step.
+    pub challenge: String,
+    /// A TOTP or recovery code.
+    pub code: String,
+}
+
+/// The secret to add to an authenticator app.
+#[derive(Debug, Serialize)]
+pub struct TotpEnrollment {
+    /// The base32 encoded secret, for entering it manually.
+    pub secret: String,
+    /// The `otpauth://` URI of the secret.
+    pub uri: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = recovery_codes)]
+struct NewRecoveryCode {
+    user: Uuid,
+    hash: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = login_challenges)]
+struct NewLoginChallenge {
+    user: Uuid,
+    hash: String,
+    expiration: NaiveDateTime,
+}
+
+/// Proof that a user entered their password correctly, which is exchanged for a
+/// `Token` together with their second factor. Only a digest of the secret is
+/// stored.
+#[derive(Debug, Queryable)]
+pub struct LoginChallenge {
+    pub id: Uuid,
+    pub user: Uuid,
+    pub hash: String,
+    pub expiration: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+
+/// A newly issued `LoginChallenge` together with its secret.
+#[derive(Debug, Serialize)]
+pub struct IssuedLoginChallenge {
+    pub challenge: String,
+    pub expiration: NaiveDateTime,
+}
+
+impl LoginChallenge {
+    /// Issues a challenge for `user`, who has entered their password.
+    pub fn issue(user: &User) -> Result<IssuedLoginChallenge, ApiError> {
+        let secret = Token::generate_secret();
+        let expiration = Utc::now()
+            .naive_utc()
+            .checked_add_signed(Duration::minutes(LOGIN_CHALLENGE_MINUTES))
+            .ok_or_else(|| ApiError::new(
+                500, "Couldn't compute expiration date of login challenge".into(),
+            ))?;
+
+        diesel::insert_into(login_challenges::table)
+            .values(NewLoginChallenge {
+                user: user.id,
+                hash: Token::hash(&secret),
+                expiration,
+            })
+            .execute(&mut db::connection()?)?;
+
+        Ok(IssuedLoginChallenge {
+            challenge: secret,
+            expiration,
+        })
+    }
+
+    /// Finds a challenge by its secret.
+    pub fn find(secret: &str) -> Result<Self, ApiError> {
+        let challenge = login_challenges::table
+            .filter(login_challenges::hash.eq(Token::hash(secret)))
+            .first::<Self>(&mut db::connection()?)
+            .map_err(|_| ApiError::new(401, "Unknown login challenge.".into()))?;
+
+        if challenge.expiration < Utc::now().naive_utc() {
+            challenge.delete()?;
+            return Err(ApiError::new(401, "Login challenge has expired.".into()));
+        }
+
+        Ok(challenge)
+    }
+
+    /// Completes the login if `code` is a valid second factor of the user the
+    /// challenge belongs to, consuming the challenge.
+    pub fn complete(&self, code: &str) -> Result<User, ApiError> {
+        let user = User::find(self.user)?;
+        if !user.verify_second_factor(code)? {
+            return Err(ApiError::new(401, "Invalid code.".into()));
+        }
+
+        self.delete()?;
+        info!("{:?} logged in", user.username);
+        Ok(user)
+    }
+
+    fn delete(&self) -> Result<(), ApiError> {
+        diesel::delete(login_challenges::table.filter(login_challenges::id.eq(self.id)))
+            .execute(&mut db::connection()?)?;
+        Ok(())
+    }
+}
+
+impl User {
+    /// Generates a new TOTP secret for the `User`, which has to be confirmed
+    /// with a code before it is required for logging in.
+    pub fn enroll_totp(&self) -> Result<TotpEnrollment, ApiError> {
+        if self.totp_enabled {
+            return Err(ApiError::new(
+                409, "Two-factor authentication is already enabled.".into(),
+            ));
+        }
+
+        let secret = totp::generate_secret();
+        diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::totp_secret.eq(totp::encode_secret(&secret)))
+            .execute(&mut db::connection()?)?;
+
+        Ok(TotpEnrollment {
+            secret: totp::encode_secret(&secret),
+            uri: totp::uri(&secret, &TOTP_ISSUER, &self.username),
+        })
+    }
+
+    /// Enables two-factor authentication if `code` matches the enrolled
+    /// secret, returning newly generated recovery codes.
+    pub fn confirm_totp(&self, code: &str) -> Result<Vec<String>, ApiError> {
+        if self.totp_enabled {
+            return Err(ApiError::new(
+                409, "Two-factor authentication is already enabled.".into(),
+            ));
+        }
+        if self.totp_secret.is_none() {
+            return Err(ApiError::new(400, "No TOTP secret enrolled.".into()));
+        }
+        if !self.verify_totp(code)? {
+            return Err(ApiError::new(403, "Invalid code.".into()));
+        }
+
+        diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::totp_enabled.eq(true))
+            .execute(&mut db::connection()?)?;
+
+        info!("{:?} enabled two-factor authentication", self.username);
+        self.regenerate_recovery_codes()
+    }
+
+    /// Disables two-factor authentication if `password` is the `User`'s
+    /// password.
+    pub fn disable_totp(&self, password: &str) -> Result<(), ApiError> {
+        if !self.verify_password(password)? {
+            return Err(ApiError::new(403, "Invalid password.".into()));
+        }
+
+        diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set((
+                users::totp_secret.eq(None::<String>),
+                users::totp_enabled.eq(false),
+                users::totp_last_step.eq(None::<i64>),
+            ))
+            .execute(&mut db::connection()?)?;
+        diesel::delete(recovery_codes::table.filter(recovery_codes::user.eq(self.id)))
+            .execute(&mut db::connection()?)?;
+
+        info!("{:?} disabled two-factor authentication", self.username);
+        Ok(())
+    }
+
+    /// Replaces the `User`'s recovery codes with new ones and returns them.
+    pub fn regenerate_recovery_codes(&self) -> Result<Vec<String>, ApiError> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+
+        let mut conn = db::connection()?;
+        conn.transaction(|conn| {
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user.eq(self.id)))
+                .execute(conn)?;
+            diesel::insert_into(recovery_codes::table)
+                .values(
+                    codes
+                        .iter()
+                        .map(|code| NewRecoveryCode {
+                            user: self.id,
+                            hash: Token::hash(&normalize_recovery_code(code)),
+                        })
+                        .collect::<Vec<_>>(),
+                )
+                .execute(conn)
+        })?;
+
+        Ok(codes)
+    }
+
+    /// Checks whether `code` is a valid TOTP code or unused recovery code of
+    /// the `User`. Accepted codes can't be used again.
+    pub fn verify_second_factor(&self, code: &str) -> Result<bool, ApiError> {
+        Ok(self.verify_totp(code)? || self.use_recovery_code(code)?)
+    }
+
+    /// Checks `code` against the `User`'s TOTP secret, remembering the time
+    /// step of accepted codes.
+    fn verify_totp(&self, code: &str) -> Result<bool, ApiError> {
+        let secret = match self.totp_secret.as_deref().and_then(totp::decode_secret) {
+            Some(secret) => secret,
+            None => return Ok(false),
+        };
+        let now = Utc::now().timestamp() as u64;
+        let last_step = self.totp_last_step.map(|step| step as u64);
+
+        match totp::verify(&secret, code, now, last_step) {
+            Some(step) => {
+                diesel::update(users::table.filter(users::id.eq(self.id)))
+                    .set(users::totp_last_step.eq(step as i64))
+                    .execute(&mut db::connection()?)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Consumes the recovery code `code` if the `User` has it.
+    fn use_recovery_code(&self, code: &str) -> Result<bool, ApiError> {
+        let deleted = diesel::delete(
+            recovery_codes::table
+                .filter(recovery_codes::user.eq(self.id))
+                .filter(recovery_codes::hash.eq(Token::hash(&normalize_recovery_code(code)))),
+        )
+        .execute(&mut db::connection()?)?;
+
+        if deleted > 0 {
+            info!("{:?} used a recovery code", self.username);
+        }
+        Ok(deleted > 0)
+    }
+}
+
+/// Generates a recovery code of the form `xxxxx-xxxxx`.
+fn generate_recovery_code() -> String {
+    const ALPHABET: &[u8] = b"abcdefghijkmnpqrstuvwxyz23456789";
+    let mut rng = rand::thread_rng();
+    let mut code: String = (0..10)
+        .map(|_| *ALPHABET.choose(&mut rng).unwrap_or(&b'a') as char)
+        .collect();
+    code.insert(5, '-');
+    code
+}
+
+/// Strips separators and whitespace from a recovery code and lowercases it.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
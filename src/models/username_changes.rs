@@ -0,0 +1,137 @@
+use crate::{
+    config, db,
+    schema::{username_changes, users},
+    ApiError, User,
+};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::prelude::*;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+lazy_static! {
+    /// How long a user has to wait between two username changes.
+    static ref USERNAME_CHANGE_COOLDOWN: Duration =
+        Duration::days(config::var_or("USERNAME_CHANGE_COOLDOWN_DAYS", 30));
+    /// How long an old username can't be taken by anyone else, so links to
+    /// it keep pointing to the same user.
+    static ref USERNAME_RESERVATION: Duration =
+        Duration::days(config::var_or("USERNAME_RESERVATION_DAYS", 90));
+}
+
+/// A request to change the authenticated user's username.
+#[derive(Debug, Deserialize, Validate)]
+pub struct NewUsername {
+    #[validate(custom = "User::valid_username")]
+    pub username: String,
+}
+
+/// A past change of a user's username.
+#[derive(Debug, Queryable, Serialize)]
+pub struct UsernameChange {
+    #[serde(skip_serializing)]
+    pub id: i32,
+    #[serde(skip_serializing)]
+    pub user: Uuid,
+    #[serde(rename = "oldUsername")]
+    pub old_username: String,
+    #[serde(rename = "newUsername")]
+    pub new_username: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = username_changes)]
+struct NewUsernameChange<'a> {
+    user: Uuid,
+    old_username: &'a str,
+    new_username: &'a str,
+}
+
+impl User {
+    /// Gives the `User` a new username. Posts and comments follow along, and
+    /// the old name stays reserved for them for a while.
+    pub fn change_username(&self, change: NewUsername) -> Result<Self, ApiError> {
+        let username = change.username.to_ascii_lowercase();
+        if username == self.username {
+            return Err(ApiError::new(400, "That's already your username.".into()));
+        }
+
+        let last_change = username_changes::table
+            .filter(username_changes::user.eq(self.id))
+            .order(username_changes::created_at.desc())
+            .first::<UsernameChange>(&mut db::connection()?)
+            .optional()?;
+        if let Some(last_change) = last_change {
+            let wait = last_change.created_at + *USERNAME_CHANGE_COOLDOWN - Utc::now().naive_utc();
+            if wait > Duration::zero() {
+                return Err(ApiError {
+                    message: format!(
+                        "You can only change your username every {} days.",
+                        USERNAME_CHANGE_COOLDOWN.num_days(),
+                    ),
+                    ..ApiError::too_many_requests(wait.to_std().unwrap_or_default())
+                });
+            }
+        }
+
+        let reclaiming = Self::reserved_by(&username)? == Some(self.id);
+        if !reclaiming && Self::username_taken(&username)? {
+            return Err(ApiError::new(409, "Username already in use.".into()));
+        }
+
+        let user = db::connection()?.transaction(|conn| {
+            diesel::insert_into(username_changes::table)
+                .values(NewUsernameChange {
+                    user: self.id,
+                    old_username: &self.username,
+                    new_username: &username,
+                })
+                .execute(conn)?;
+            diesel::update(users::table.filter(users::id.eq(self.id)))
+                .set(users::username.eq(&username))
+                .get_result::<Self>(conn)
+        })?;
+
+        info!("{:?} is now called {:?}", self.username, user.username);
+        Ok(user)
+    }
+
+    /// Returns the `User`'s past username changes, newest first.
+    pub fn username_changes(&self) -> Result<Vec<UsernameChange>, ApiError> {
+        Ok(username_changes::table
+            .filter(username_changes::user.eq(self.id))
+            .order(username_changes::created_at.desc())
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Returns the id of the user who gave up `username` recently enough for
+    /// it to still be reserved.
+    pub(crate) fn reserved_by(username: &str) -> Result<Option<Uuid>, ApiError> {
+        Ok(username_changes::table
+            .select(username_changes::user)
+            .filter(username_changes::old_username.eq(username.to_ascii_lowercase()))
+            .filter(
+                username_changes::created_at
+                    .gt(Utc::now().naive_utc() - *USERNAME_RESERVATION),
+            )
+            .order(username_changes::created_at.desc())
+            .first(&mut db::connection()?)
+            .optional()?)
+    }
+
+    /// Finds the `User` who last went by `username` before renaming
+    /// themselves, if nobody uses the name now.
+    pub fn renamed_from(username: &str) -> Result<Option<Self>, ApiError> {
+        let user = username_changes::table
+            .select(username_changes::user)
+            .filter(username_changes::old_username.eq(username.to_ascii_lowercase()))
+            .order(username_changes::created_at.desc())
+            .first(&mut db::connection()?)
+            .optional()?;
+
+        user.map(Self::find).transpose()
+    }
+}
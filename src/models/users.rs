@@ -1,15 +1,24 @@
 use crate::{
-    db,
+    config, db,
+    models::profiles::nullable,
+    password,
     schema::{posts, tokens, users},
-    ApiError, Post,
+    ApiError, ApiKey, ClientInfo, Credential, IssuedToken, NewToken, PasswordChange, Post, Role,
+    Token, EMAIL_REQUIRED,
 };
-use chrono::{Duration, NaiveDateTime, Utc};
+use chrono::{NaiveDateTime, Utc};
 use diesel::prelude::*;
-use rand::Rng;
+use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::{Validate, ValidationError};
 
+lazy_static! {
+    /// A password hash to verify against when logging in as an unknown user.
+    static ref DUMMY_PASSWORD_HASH: String =
+        User::hash_password("dummy password").expect("Couldn't hash dummy password");
+}
+
 #[derive(Debug, Deserialize, Insertable, Validate)]
 #[diesel(table_name = users)]
 pub struct Registration {
@@ -17,6 +26,8 @@ pub struct Registration {
     pub username: String,
     #[validate(length(min = 6))]
     pub password: String,
+    #[validate(email, length(max = 254))]
+    pub email: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,87 +36,6 @@ pub struct Login {
     pub password: String,
 }
 
-#[derive(Debug, Insertable)]
-#[diesel(table_name = tokens)]
-pub struct NewToken {
-    pub user: Uuid,
-    pub expiration: NaiveDateTime,
-}
-
-impl NewToken {
-    pub fn new(user: Uuid) -> Result<Self, ApiError> {
-        Ok(NewToken {
-            user,
-            expiration: Utc::now()
-                .checked_add_signed(Duration::days(14))
-                .ok_or_else(|| ApiError::new(
-                    500, "Couldn't compute expiration date of token".into(),
-                ))?
-                .naive_utc(),
-        })
-    }
-}
-
-/// A token is a secret used to authenticate a user and is created when the user
-/// logs in.
-#[derive(Debug, Queryable, Serialize)]
-pub struct Token {
-    /// The id of the token.
-    pub id: Uuid,
-    /// The id of the user the token belongs to.
-    #[serde(skip_serializing)]
-    pub user: Uuid,
-    /// The expiration date of the token.
-    pub expiration: NaiveDateTime,
-    /// The date the token was created.
-    #[serde(rename = "createdAt")]
-    pub created_at: NaiveDateTime,
-    /// The date the token was last updated.
-    #[serde(rename = "updatedAt")]
-    pub updated_at: Option<NaiveDateTime>,
-}
-
-impl Token {
-    /// Finds a `Token` by its `id`.
-    pub fn find(id: Uuid) -> Result<Self, ApiError> {
-        let token = tokens::table
-            .filter(tokens::id.eq(id))
-            .first::<Token>(&mut db::connection()?)?;
-
-        if token.expiration < Utc::now().naive_utc() {
-            diesel::delete(tokens::table.filter(tokens::id.eq(id)))
-                .execute(&mut db::connection()?)?;
-            Err(ApiError::new(404, "Token has expired.".into()))
-        } else {
-            Ok(token)
-        }
-    }
-
-    /// Deletes a `Token`.
-    pub fn delete(&self) -> Result<Self, ApiError> {
-        Ok(diesel::delete(tokens::table)
-            .filter(tokens::id.eq(self.id))
-            .get_result(&mut db::connection()?)?)
-    }
-}
-
-impl TryFrom<NewToken> for Token {
-    type Error = ApiError;
-
-    fn try_from(token: NewToken) -> Result<Self, Self::Error> {
-        let token = diesel::insert_into(tokens::table)
-            .values(token)
-            .get_result(&mut db::connection()?)?;
-        Ok(token)
-    }
-}
-
-/// A session has a token used for authentication.
-#[derive(Debug, Deserialize)]
-pub struct Session {
-    pub token: Uuid,
-}
-
 #[derive(Debug, Queryable, Serialize)]
 pub struct User {
     #[serde(skip_serializing)]
@@ -118,6 +48,29 @@ pub struct User {
     pub created_at: NaiveDateTime,
     #[serde(skip_serializing)]
     pub updated_at: Option<NaiveDateTime>,
+    pub role: Role,
+    /// The base32 encoded TOTP secret, set once the user starts enrolling.
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    /// Whether logging in requires a TOTP or recovery code.
+    #[serde(skip_serializing)]
+    pub totp_enabled: bool,
+    /// The last time step a TOTP code was accepted for.
+    #[serde(skip_serializing)]
+    pub totp_last_step: Option<i64>,
+    #[serde(skip_serializing)]
+    pub email: Option<String>,
+    /// Whether the user followed the verification link sent to `email`.
+    #[serde(skip_serializing)]
+    pub email_verified: bool,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+    pub website: Option<String>,
+    pub links: Vec<String>,
+    pub location: Option<String>,
+    pub pronouns: Option<String>,
+    #[serde(rename = "avatarUrl")]
+    pub avatar_url: Option<String>,
 }
 
 impl User {
@@ -135,6 +88,17 @@ impl User {
             .first(&mut db::connection()?)?)
     }
 
+    /// Checks whether a `User` with the given name exists or the name is
+    /// still reserved after a username change.
+    pub fn username_taken(username: &str) -> Result<bool, ApiError> {
+        let exists = diesel::select(diesel::dsl::exists(
+            users::table.filter(users::username.eq(username.to_ascii_lowercase())),
+        ))
+        .get_result(&mut db::connection()?)?;
+
+        Ok(exists || Self::reserved_by(username)?.is_some())
+    }
+
     /// Finds a `User`'s posts.
     pub fn posts(&self) -> Result<Vec<Post>, ApiError> {
         Ok(posts::table
@@ -142,23 +106,154 @@ impl User {
             .load(&mut db::connection()?)?)
     }
 
-    /// Generates and returns a new `Token`.
-    pub fn get_token(&self) -> Result<Token, ApiError> {
-        Token::try_from(NewToken::new(self.id)?)
+    /// Returns the `User`'s active `Token`s, newest first.
+    pub fn tokens(&self) -> Result<Vec<Token>, ApiError> {
+        Ok(tokens::table
+            .filter(tokens::user.eq(self.id))
+            .filter(tokens::expiration.gt(Utc::now().naive_utc()))
+            .order(tokens::created_at.desc())
+            .load(&mut db::connection()?)?)
+    }
+
+    /// Generates and returns a new `Token` for a session from `client`.
+    pub fn get_token(&self, client: ClientInfo) -> Result<IssuedToken, ApiError> {
+        let secret = Token::generate_secret();
+        let token = Token::try_from(NewToken::new(self.id, &secret, client)?)?;
+
+        Ok(IssuedToken {
+            token: secret,
+            details: token,
+        })
     }
 
-    /// Updates the users preferences.
-    pub fn update(&self, update: UserUpdate) -> Result<Self, ApiError> {
+    /// Deletes all of the `User`'s `Token`s except for the one with the id
+    /// `except`, returning the number of deleted tokens.
+    pub fn revoke_tokens(&self, except: Option<Uuid>) -> Result<usize, ApiError> {
+        let mut query = diesel::delete(tokens::table)
+            .filter(tokens::user.eq(self.id))
+            .into_boxed();
+
+        if let Some(id) = except {
+            query = query.filter(tokens::id.ne(id));
+        }
+
+        Ok(query.execute(&mut db::connection()?)?)
+    }
+
+    /// Hashes a password for storage.
+    pub fn hash_password(password: &str) -> Result<String, ApiError> {
+        password::hash(password)
+    }
+
+    /// Checks whether `password` is the `User`'s password.
+    pub fn verify_password(&self, password: &str) -> Result<bool, ApiError> {
+        password::verify(&self.password, password)
+    }
+
+    /// Replaces the `User`'s password.
+    pub fn set_password(&self, password: &str) -> Result<Self, ApiError> {
+        Ok(diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::password.eq(Self::hash_password(password)?))
+            .get_result(&mut db::connection()?)?)
+    }
+
+    /// Changes the `User`'s password if the current password matches and
+    /// revokes all sessions except the one with the id `session`.
+    pub fn change_password(
+        &self, change: PasswordChange, session: Uuid,
+    ) -> Result<Self, ApiError> {
+        if !self.verify_password(&change.current_password)? {
+            return Err(ApiError::new(403, "Invalid password.".into()));
+        }
+
+        let user = self.set_password(&change.new_password)?;
+        user.revoke_tokens(Some(session))?;
+
+        info!("{:?} changed their password", user.username);
+        Ok(user)
+    }
+
+    /// Gives the `User` a new `Role`.
+    pub fn set_role(&self, role: Role) -> Result<Self, ApiError> {
+        let user: Self = diesel::update(users::table.filter(users::id.eq(self.id)))
+            .set(users::role.eq(role))
+            .get_result(&mut db::connection()?)?;
+
+        info!("{:?} is now {}", user.username, user.role);
+        Ok(user)
+    }
+
+    /// Makes the user called `INITIAL_ADMIN` an admin as long as there is
+    /// none yet, since only admins can change roles.
+    pub fn create_initial_admin() -> Result<(), ApiError> {
+        let username: String = config::var_or("INITIAL_ADMIN", String::new());
+        if username.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = db::connection()?;
+        let admin_exists = diesel::select(diesel::dsl::exists(
+            users::table.filter(users::role.eq(Role::Admin)),
+        ))
+        .get_result(&mut conn)?;
+        if admin_exists {
+            return Ok(());
+        }
+
+        match Self::by_name(username.to_ascii_lowercase()) {
+            Ok(user) => {
+                user.set_role(Role::Admin)?;
+            }
+            Err(e) if e.status_code == 404 => {
+                warn!("INITIAL_ADMIN {:?} doesn't exist yet, register and restart", username)
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Updates the users preferences. Changing the email address requires
+    /// verifying the new one.
+    pub fn update(&self, mut update: UserUpdate) -> Result<Self, ApiError> {
+        let trim = |s: Option<String>| s.map(|s| s.trim().to_owned()).filter(|s| !s.is_empty());
+        update.email = update.email.map(|email| email.trim().to_owned());
+        update.display_name = update.display_name.map(trim);
+        update.location = update.location.map(trim);
+        update.pronouns = update.pronouns.map(trim);
+        let email_changed = match (&update.email, &self.email) {
+            (Some(new), Some(old)) => !new.eq_ignore_ascii_case(old),
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+
+        if email_changed {
+            if User::email_taken(update.email.as_deref().unwrap_or_default())? {
+                return Err(ApiError::new(409, "Email address already in use.".into()));
+            }
+
+            let user: Self = diesel::update(users::table.filter(users::id.eq(self.id)))
+                .set((update, users::email_verified.eq(false)))
+                .get_result(&mut db::connection()?)?;
+            info!("{:?} changed their email address", user.username);
+            user.try_send_email_verification();
+            return Ok(user);
+        }
+
         Ok(diesel::update(users::table.filter(users::id.eq(self.id)))
             .set(update)
             .get_result(&mut db::connection()?)?)
     }
 
-    /// Returns the `User` the `Token` belongs to if the `Token` is valid and an
-    /// `ApiError` if it is unknown (or expired, in which case it is also
-    /// deleted).
-    pub fn from_token(token: Uuid) -> Result<Self, ApiError> {
-        let token = Token::find(token).map_err(|e| {
+    /// Returns the `User` the session token or API key belongs to together
+    /// with the `Credential` if it is valid and an `ApiError` if it is unknown
+    /// (or expired, in which case a session token is also deleted).
+    pub fn from_token(token: &str) -> Result<(Self, Credential), ApiError> {
+        let credential = if token.starts_with(ApiKey::PREFIX) {
+            ApiKey::find(token).map(Credential::ApiKey)
+        } else {
+            Token::find(token).map(Credential::Session)
+        }
+        .map_err(|e| {
             if e.status_code == 404 {
                 ApiError::new(401, "Unknown token.".into())
             } else {
@@ -166,7 +261,7 @@ impl User {
             }
         })?;
 
-        Self::find(token.user)
+        Ok((Self::find(credential.user())?, credential))
     }
 
     /// Checks if the given `&str` constitutes a valid username.
@@ -213,17 +308,42 @@ impl TryFrom<Login> for User {
     type Error = ApiError;
 
     fn try_from(login: Login) -> Result<Self, Self::Error> {
-        let user = User::by_name(login.username)
-            .map_err(|_| ApiError::new(404, "Unknown user.".into()))?;
-
-        if argon2::verify_encoded(&user.password, login.password.as_bytes())
-            .map_err(|e| ApiError::new(500, format!("Couldn't verify hash: {}", e)))?
-        {
-            info!("{:?} logged in", user.username);
-            Ok(user)
-        } else {
-            Err(ApiError::new(401, "Invalid password.".into()))
+        let invalid = || ApiError::new(401, "Invalid username or password.".into());
+        let user = match User::by_name(login.username) {
+            Ok(user) if user.username == User::DELETED => return Err(invalid()),
+            Ok(user) => user,
+            Err(e) if e.status_code == 404 => {
+                // Take as long as checking a real password would, so response
+                // times don't reveal whether the user exists.
+                password::verify(&DUMMY_PASSWORD_HASH, &login.password).ok();
+                return Err(invalid());
+            }
+            Err(e) => return Err(e),
+        };
+
+        if !user.verify_password(&login.password)? {
+            return Err(invalid());
         }
+
+        // Upgrade the hash while we know the password, but don't fail the
+        // login if that doesn't work.
+        let user = if password::needs_rehash(&user.password) {
+            match user.set_password(&login.password) {
+                Ok(user) => {
+                    info!("Upgraded password hash of {:?}", user.username);
+                    user
+                }
+                Err(e) => {
+                    warn!("Couldn't upgrade password hash of {:?}: {}", user.username, e);
+                    user
+                }
+            }
+        } else {
+            user
+        };
+
+        info!("{:?} logged in", user.username);
+        Ok(user)
     }
 }
 
@@ -231,27 +351,36 @@ impl TryFrom<Registration> for User {
     type Error = ApiError;
 
     fn try_from(user: Registration) -> Result<Self, Self::Error> {
-        let salt: [u8; 32] = rand::thread_rng().gen();
-        let config = argon2::Config::default();
-        let hash = argon2::hash_encoded(user.password.as_bytes(), &salt, &config)
-            .map_err(|e| ApiError::new(500, format!("Couldn't hash password: {}", e)))?;
-        let user = Registration {
-            password: hash,
+        let mut user = Registration {
+            password: Self::hash_password(&user.password)?,
             username: user.username.to_ascii_lowercase(),
+            email: user.email.map(|email| email.trim().to_owned()),
         };
 
-        if users::table
-                .filter(users::username.eq(user.username.to_owned()))
-                .first::<User>(&mut db::connection()?)
-                .is_ok() {
+        if User::username_taken(&user.username)? {
             return Err(ApiError::new(409, "Username already in use.".into()));
         }
+        // A taken address is left out and its owner notified, so registering
+        // doesn't reveal which addresses have accounts.
+        let mut taken_email = None;
+        match &user.email {
+            Some(email) if User::email_taken(email)? => taken_email = user.email.take(),
+            None if *EMAIL_REQUIRED => {
+                return Err(ApiError::new(400, "An email address is required.".into()));
+            }
+            _ => {}
+        }
 
         let user = diesel::insert_into(users::table)
             .values(user)
             .get_result::<Self>(&mut db::connection()?)?;
 
         info!("Registered {:?}", user.username);
+        if let Some(email) = taken_email {
+            User::notify_email_in_use(&email, &user.username);
+        } else if user.email.is_some() {
+            user.try_send_email_verification();
+        }
 
         Ok(user)
     }
@@ -262,4 +391,24 @@ impl TryFrom<Registration> for User {
 pub struct UserUpdate {
     #[validate(length(max = 160))]
     pub about: Option<String>,
+    #[validate(email, length(max = 254))]
+    pub email: Option<String>,
+    /// Like the other profile fields, it's cleared when set to `null`.
+    #[serde(rename = "displayName", default, deserialize_with = "nullable")]
+    #[validate(custom = "User::valid_display_name")]
+    pub display_name: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(custom = "User::valid_link")]
+    pub website: Option<Option<String>>,
+    #[validate(custom = "User::valid_links")]
+    pub links: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(min = 1, max = 100))]
+    pub location: Option<Option<String>>,
+    #[serde(default, deserialize_with = "nullable")]
+    #[validate(length(min = 1, max = 30))]
+    pub pronouns: Option<Option<String>>,
+    #[serde(rename = "avatarUrl", default, deserialize_with = "nullable")]
+    #[validate(custom = "User::valid_link")]
+    pub avatar_url: Option<Option<String>>,
 }
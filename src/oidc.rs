@@ -0,0 +1,280 @@
+//! A minimal OpenID Connect client implementing the authorization code flow
+//! with PKCE.
+
+use crate::{config, ApiError};
+use data_encoding::BASE64URL_NOPAD;
+use rand::Rng;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+/// An OpenID Connect provider users can log in with, configured through the
+/// `OIDC_<NAME>_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    /// The name used in URLs and to link identities.
+    pub name: String,
+    /// The issuer URL, which the discovery document is fetched from.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    /// The URL the provider redirects users back to after authorization.
+    pub redirect_url: String,
+}
+
+/// The parts of a provider's discovery document we need.
+#[derive(Debug, Deserialize)]
+pub struct Metadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// A value that can be either a single string or a list of strings.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    fn contains(&self, value: &str) -> bool {
+        match self {
+            Self::One(s) => s == value,
+            Self::Many(v) => v.iter().any(|s| s == value),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawClaims {
+    iss: String,
+    sub: String,
+    aud: OneOrMany,
+    exp: i64,
+    nonce: Option<String>,
+    preferred_username: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+    name: Option<String>,
+}
+
+/// The validated claims of an ID token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Claims {
+    /// The identifier of the user at the provider.
+    pub subject: String,
+    pub preferred_username: Option<String>,
+    pub email: Option<String>,
+    /// Whether the provider has verified that the email address belongs to
+    /// the user.
+    pub email_verified: bool,
+    pub name: Option<String>,
+}
+
+/// The secrets of an authorization request that are needed again when the
+/// user returns.
+#[derive(Debug, Clone)]
+pub struct AuthorizationRequest {
+    /// The URL to send the user to.
+    pub url: String,
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl Provider {
+    /// Reads the configuration of the provider `name` from the environment,
+    /// returning `None` if it isn't configured.
+    pub fn from_env(name: &str) -> Option<Self> {
+        let var = |key: &str| {
+            let value: String = config::var_or(
+                &format!("OIDC_{}_{}", name.to_ascii_uppercase(), key),
+                String::new(),
+            );
+            Some(value).filter(|s| !s.is_empty())
+        };
+
+        Some(Provider {
+            name: name.to_owned(),
+            issuer: var("ISSUER")?,
+            client_id: var("CLIENT_ID")?,
+            client_secret: var("CLIENT_SECRET"),
+            redirect_url: var("REDIRECT_URL")?,
+        })
+    }
+
+    /// Finds a provider listed in `OIDC_PROVIDERS` by its name.
+    pub fn find(name: &str) -> Result<Self, ApiError> {
+        let providers: String = config::var_or("OIDC_PROVIDERS", String::new());
+        providers
+            .split(',')
+            .map(str::trim)
+            .find(|provider| *provider == name)
+            .and_then(Self::from_env)
+            .ok_or_else(|| ApiError::new(404, "Unknown identity provider.".into()))
+    }
+
+    /// Fetches the provider's discovery document.
+    pub async fn discover(&self) -> Result<Metadata, ApiError> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            self.issuer.trim_end_matches('/')
+        );
+        let metadata: Metadata = awc::Client::default()
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| ApiError::new(502, format!("Couldn't reach identity provider: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| ApiError::new(502, format!("Invalid discovery document: {}", e)))?;
+
+        if metadata.issuer.trim_end_matches('/') != self.issuer.trim_end_matches('/') {
+            return Err(ApiError::new(502, "Identity provider issuer mismatch.".into()));
+        }
+
+        Ok(metadata)
+    }
+
+    /// Creates a new authorization request with fresh secrets.
+    pub fn authorize(&self, metadata: &Metadata) -> Result<AuthorizationRequest, ApiError> {
+        let state = random_string();
+        let nonce = random_string();
+        let code_verifier = random_string();
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.client_id),
+                ("redirect_uri", &self.redirect_url),
+                ("scope", "openid profile email"),
+                ("state", &state),
+                ("nonce", &nonce),
+                ("code_challenge", &code_challenge(&code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| ApiError::new(500, format!("Invalid authorization endpoint: {}", e)))?;
+
+        Ok(AuthorizationRequest {
+            url: url.into(),
+            state,
+            nonce,
+            code_verifier,
+        })
+    }
+
+    /// Exchanges an authorization code for an ID token and returns its
+    /// validated claims.
+    pub async fn exchange(
+        &self,
+        metadata: &Metadata,
+        code: &str,
+        code_verifier: &str,
+        nonce: &str,
+    ) -> Result<Claims, ApiError> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_url),
+            ("client_id", &self.client_id),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let mut response = awc::Client::default()
+            .post(&metadata.token_endpoint)
+            .send_form(&form)
+            .await
+            .map_err(|e| ApiError::new(502, format!("Couldn't reach identity provider: {}", e)))?;
+        if !response.status().is_success() {
+            return Err(ApiError::new(401, "Identity provider rejected the code.".into()));
+        }
+        let token: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| ApiError::new(502, format!("Invalid token response: {}", e)))?;
+
+        self.validate_id_token(
+            metadata,
+            &token.id_token,
+            nonce,
+            chrono::Utc::now().timestamp(),
+        )
+    }
+
+    /// Validates the claims of an ID token received directly from the token
+    /// endpoint.
+    ///
+    /// As the token comes straight from the provider over TLS, its signature
+    /// isn't checked (see section 3.1.3.7 of OpenID Connect Core).
+    pub fn validate_id_token(
+        &self,
+        metadata: &Metadata,
+        id_token: &str,
+        nonce: &str,
+        now: i64,
+    ) -> Result<Claims, ApiError> {
+        let invalid = |reason: &str| ApiError::new(401, format!("Invalid ID token: {}", reason));
+
+        let payload = id_token.split('.').nth(1).ok_or_else(|| invalid("malformed"))?;
+        let payload = BASE64URL_NOPAD
+            .decode(payload.trim_end_matches('=').as_bytes())
+            .map_err(|_| invalid("malformed"))?;
+        let claims: RawClaims =
+            serde_json::from_slice(&payload).map_err(|_| invalid("malformed"))?;
+
+        if claims.iss != metadata.issuer {
+            return Err(invalid("wrong issuer"));
+        }
+        if !claims.aud.contains(&self.client_id) {
+            return Err(invalid("wrong audience"));
+        }
+        if claims.exp <= now {
+            return Err(invalid("expired"));
+        }
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("wrong nonce"));
+        }
+
+        Ok(Claims {
+            subject: claims.sub,
+            preferred_username: claims.preferred_username,
+            email: claims.email,
+            email_verified: claims.email_verified,
+            name: claims.name,
+        })
+    }
+}
+
+/// Derives the PKCE code challenge from a code verifier using the `S256`
+/// method.
+///
+/// ```
+/// use ephemeris::oidc::code_challenge;
+///
+/// // Example from RFC 7636.
+/// assert_eq!(
+///     code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+///     "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM",
+/// );
+/// ```
+pub fn code_challenge(code_verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Generates a random URL-safe string.
+fn random_string() -> String {
+    BASE64URL_NOPAD.encode(&rand::thread_rng().gen::<[u8; 32]>())
+}
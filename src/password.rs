@@ -0,0 +1,144 @@
+//! Password hashing with argon2, using parameters configured through the
+//! environment.
+
+use crate::{config, ApiError};
+use argon2::{Config, Variant, Version};
+use lazy_static::lazy_static;
+use rand::Rng;
+
+lazy_static! {
+    /// The parameters new password hashes are created with.
+    static ref PARAMS: Params = Params {
+        variant: Variant::from_str(&config::var_or("ARGON2_VARIANT", "argon2id".to_string()))
+            .expect("ARGON2_VARIANT must be argon2d, argon2i or argon2id"),
+        version: Version::Version13,
+        mem_cost: config::var_or("ARGON2_MEMORY_KIB", 19456),
+        time_cost: config::var_or("ARGON2_ITERATIONS", 2),
+        lanes: config::var_or("ARGON2_PARALLELISM", 1),
+    };
+}
+
+/// The parameters of an argon2 hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    pub variant: Variant,
+    pub version: Version,
+    /// The memory cost in KiB.
+    pub mem_cost: u32,
+    /// The number of iterations.
+    pub time_cost: u32,
+    /// The degree of parallelism.
+    pub lanes: u32,
+}
+
+impl Params {
+    /// Reads the parameters from an encoded hash.
+    ///
+    /// ```
+    /// use argon2::{Variant, Version};
+    /// use ephemeris::password::Params;
+    ///
+    /// let params = Params::parse("$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA").unwrap();
+    /// assert_eq!(params, Params {
+    ///     variant: Variant::Argon2id,
+    ///     version: Version::Version13,
+    ///     mem_cost: 19456,
+    ///     time_cost: 2,
+    ///     lanes: 1,
+    /// });
+    ///
+    /// // Hashes of version 0x10 don't include the version.
+    /// let params = Params::parse("$argon2i$m=4096,t=3,p=1$c2FsdHNhbHQ$aGFzaA").unwrap();
+    /// assert_eq!(params.version, Version::Version10);
+    /// ```
+    pub fn parse(encoded: &str) -> Option<Self> {
+        let mut parts = encoded.split('$').skip(1);
+        let variant = Variant::from_str(parts.next()?).ok()?;
+        let mut part = parts.next()?;
+        let version = match part.strip_prefix("v=") {
+            Some(version) => {
+                part = parts.next()?;
+                Version::from_str(version).ok()?
+            }
+            None => Version::Version10,
+        };
+
+        let (mut mem_cost, mut time_cost, mut lanes) = (None, None, None);
+        for param in part.split(',') {
+            let (key, value) = param.split_once('=')?;
+            let value = value.parse().ok();
+            match key {
+                "m" => mem_cost = value,
+                "t" => time_cost = value,
+                "p" => lanes = value,
+                _ => return None,
+            }
+        }
+
+        Some(Params {
+            variant,
+            version,
+            mem_cost: mem_cost?,
+            time_cost: time_cost?,
+            lanes: lanes?,
+        })
+    }
+
+    /// Whether a hash with these parameters should be replaced by one with
+    /// `other`'s.
+    ///
+    /// ```
+    /// use argon2::{Variant, Version};
+    /// use ephemeris::password::Params;
+    ///
+    /// let current = Params {
+    ///     variant: Variant::Argon2id,
+    ///     version: Version::Version13,
+    ///     mem_cost: 19456,
+    ///     time_cost: 2,
+    ///     lanes: 1,
+    /// };
+    ///
+    /// assert!(!current.is_weaker_than(&current));
+    /// assert!(Params { mem_cost: 4096, ..current }.is_weaker_than(&current));
+    /// assert!(Params { variant: Variant::Argon2i, ..current }.is_weaker_than(&current));
+    /// assert!(!Params { time_cost: 3, ..current }.is_weaker_than(&current));
+    /// ```
+    pub fn is_weaker_than(&self, other: &Self) -> bool {
+        self.variant != other.variant
+            || self.version.as_u32() < other.version.as_u32()
+            || self.mem_cost < other.mem_cost
+            || self.time_cost < other.time_cost
+            || self.lanes < other.lanes
+    }
+
+    fn config(&self) -> Config<'static> {
+        Config {
+            variant: self.variant,
+            version: self.version,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            lanes: self.lanes,
+            ..Config::default()
+        }
+    }
+}
+
+/// Hashes a password with the configured parameters.
+pub fn hash(password: &str) -> Result<String, ApiError> {
+    let salt: [u8; 32] = rand::thread_rng().gen();
+    argon2::hash_encoded(password.as_bytes(), &salt, &PARAMS.config())
+        .map_err(|e| ApiError::new(500, format!("Couldn't hash password: {}", e)))
+}
+
+/// Checks whether `password` matches the encoded hash.
+pub fn verify(encoded: &str, password: &str) -> Result<bool, ApiError> {
+    argon2::verify_encoded(encoded, password.as_bytes())
+        .map_err(|e| ApiError::new(500, format!("Couldn't verify hash: {}", e)))
+}
+
+/// Whether the encoded hash was created with weaker parameters than the
+/// configured ones.
+pub fn needs_rehash(encoded: &str) -> bool {
+    Params::parse(encoded).is_none_or(|params| params.is_weaker_than(&PARAMS))
+}
@@ -0,0 +1,110 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// The number of tracked keys above which stale entries are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Limits attempts per key (e.g. an IP address or a username) with
+/// exponential backoff.
+///
+/// The first `free_attempts` attempts are free. Every attempt after that
+/// blocks the key for `base_delay`, doubling with each further attempt up to
+/// `max_delay`. A key is forgotten once it hasn't been recorded for
+/// `max_delay` after it was last unblocked.
+///
+/// ```
+/// use ephemeris::rate_limit::RateLimiter;
+/// use std::time::Duration;
+///
+/// let limiter = RateLimiter::new(2, Duration::from_secs(60), Duration::from_secs(600));
+///
+/// limiter.record("alice");
+/// assert!(limiter.retry_after("alice").is_none());
+///
+/// limiter.record("alice");
+/// assert!(limiter.retry_after("alice").unwrap() > Duration::from_secs(59));
+/// assert!(limiter.retry_after("bob").is_none());
+///
+/// limiter.reset("alice");
+/// assert!(limiter.retry_after("alice").is_none());
+/// ```
+pub struct RateLimiter {
+    free_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    attempts: u32,
+    blocked_until: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(free_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            free_attempts,
+            base_delay,
+            max_delay,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns how long `key` has to wait until its next attempt, if at all.
+    pub fn retry_after(&self, key: &str) -> Option<Duration> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .and_then(|entry| entry.blocked_until.checked_duration_since(Instant::now()))
+            .filter(|wait| !wait.is_zero())
+    }
+
+    /// Records an attempt by `key` counting towards its limit.
+    pub fn record(&self, key: &str) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        if entries.len() >= PRUNE_THRESHOLD {
+            entries.retain(|_, entry| !self.is_stale(entry, now));
+        }
+
+        let entry = entries.entry(key.to_owned()).or_insert(Entry {
+            attempts: 0,
+            blocked_until: now,
+        });
+        if self.is_stale(entry, now) {
+            entry.attempts = 0;
+        }
+
+        entry.attempts += 1;
+        entry.blocked_until = now + self.delay(entry.attempts);
+    }
+
+    /// Forgets the recorded attempts of `key`, e.g. after a successful login.
+    pub fn reset(&self, key: &str) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+
+    /// The time a key is blocked for after `attempts` attempts.
+    fn delay(&self, attempts: u32) -> Duration {
+        if attempts < self.free_attempts {
+            return Duration::ZERO;
+        }
+
+        let exponent = (attempts - self.free_attempts).min(31);
+        self.base_delay
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+
+    fn is_stale(&self, entry: &Entry, now: Instant) -> bool {
+        entry.blocked_until + self.max_delay < now
+    }
+}
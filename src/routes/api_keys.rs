@@ -0,0 +1,42 @@
+use crate::{ApiError, ApiKey, AuthenticatedUser, IssuedApiKey, NewApiKey};
+use actix_web::{
+    delete, get, post,
+    web::{self, Json, Path},
+    HttpResponse,
+};
+use uuid::Uuid;
+use validator::Validate;
+
+#[get("/api-keys")]
+async fn find_all(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    Ok(HttpResponse::Ok().json(auth.user.api_keys()?))
+}
+
+#[post("/api-keys")]
+async fn create(
+    key: Json<NewApiKey>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let key = key.into_inner();
+    key.validate()?;
+    let key = IssuedApiKey::try_from((key, &auth.user))?;
+    Ok(HttpResponse::Created().json(key))
+}
+
+#[delete("/api-keys/{id}")]
+async fn delete(id: Path<Uuid>, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let key = ApiKey::by_id(id.into_inner())?;
+    if key.user != auth.user.id {
+        return Err(ApiError::new(404, "Record not found".into()));
+    }
+    key.delete()?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(find_all);
+    cfg.service(create);
+    cfg.service(delete);
+}
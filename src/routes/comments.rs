@@ -1,67 +1,94 @@
 use actix_web::{get, post, delete, put, web::{self, Path, Json}, HttpResponse};
-use uuid::Uuid;
-use serde::Deserialize;
 use validator::Validate;
-use crate::{CommentFilters, ApiError, Comment, Session, User, NewComment, UpdateComment};
+use crate::{
+    Action, CommentFilters, ApiError, AuthenticatedUser, Comment, NewComment,
+    RevisionDiffQuery, Scope, UpdateComment,
+};
 
 #[get("/comments")]
 async fn find_all(
-    filters: web::Query<CommentFilters>,
+    filters: web::Query<CommentFilters>, auth: Option<AuthenticatedUser>,
 ) -> Result<HttpResponse, ApiError> {
-    let comments = Comment::find_all(filters.into_inner())?;
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let comments = Comment::find_all(filters.into_inner(), viewer)?;
     Ok(HttpResponse::Ok().json(comments))
 }
 
 #[get("/comment/{id}")]
-async fn find(id: Path<i32>) -> Result<HttpResponse, ApiError> {
-    let comment = Comment::find(id.into_inner())?;
+async fn find(id: Path<i32>, auth: Option<AuthenticatedUser>) -> Result<HttpResponse, ApiError> {
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let comment = Comment::find(id.into_inner(), viewer)?;
     Ok(HttpResponse::Ok().json(comment))
 }
 
 #[delete("/comment/{id}")]
-async fn delete(id: Path<i32>, session: Json<Session>) -> Result<HttpResponse, ApiError> {
-    let Session { token } = session.into_inner();
-    let user = User::from_token(token)?;
-    let comment = Comment::find(id.into_inner())?;
-
-    if user.username == comment.author {
-        Ok(HttpResponse::Ok().json(comment.delete()?))
-    } else {
-        Err(ApiError::new(403, "You can't delete this comment.".into()))
-    }
-}
-
-#[derive(Deserialize)]
-struct CreateMessage {
-    pub comment: NewComment,
-    pub token: Uuid,
+async fn delete(id: Path<i32>, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::CommentsWrite)?;
+    let comment = Comment::find_for_edit(id.into_inner(), &auth.user)?;
+    auth.user.authorize(Action::Delete, &comment)?;
+    Ok(HttpResponse::Ok().json(comment.delete()?))
 }
 
 #[post("/comment")]
-async fn create(data: Json<CreateMessage>) -> Result<HttpResponse, ApiError> {
-    let CreateMessage { comment, token } = data.into_inner();
+async fn create(
+    comment: Json<NewComment>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::CommentsWrite)?;
+    let comment = comment.into_inner();
     comment.validate()?;
-    let author = User::from_token(token)?;
-    let comment = Comment::try_from((comment, &author))?;
+    let comment = Comment::try_from((comment, &auth.user))?;
     Ok(HttpResponse::Created().json(comment))
 }
 
-#[derive(Deserialize)]
-struct EditMessage {
-    pub comment: UpdateComment,
-    pub token: Uuid,
-}
-
 #[put("/comment")]
-async fn edit(data: Json<EditMessage>) -> Result<HttpResponse, ApiError> {
-    let EditMessage { comment, token } = data.into_inner();
+async fn edit(
+    comment: Json<UpdateComment>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::CommentsWrite)?;
+    let comment = comment.into_inner();
     comment.validate()?;
-    let old_comment = Comment::find(comment.id)?;
-    let user = User::from_token(token)?;
-    if user.username != old_comment.author {
-        return Err(ApiError::new(403, "You can't edit this comment.".into()));
-    }
-    Ok(HttpResponse::Ok().json(old_comment.edit(comment)?))
+    let old_comment = Comment::find_for_edit(comment.id, &auth.user)?;
+    auth.user.authorize(Action::Edit, &old_comment)?;
+    Ok(HttpResponse::Ok().json(old_comment.edit(comment, &auth.user)?))
+}
+
+#[get("/comment/{id}/revisions")]
+async fn find_revisions(
+    id: Path<i32>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let comment = Comment::find(id.into_inner(), viewer)?;
+    Ok(HttpResponse::Ok().json(comment.revisions()?))
+}
+
+#[get("/comment/{id}/revisions/{revision}")]
+async fn find_revision(
+    path: Path<(i32, i32)>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, revision) = path.into_inner();
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let comment = Comment::find(id, viewer)?;
+    Ok(HttpResponse::Ok().json(comment.revision(Some(revision))?))
+}
+
+#[get("/comment/{id}/diff")]
+async fn diff(
+    id: Path<i32>, query: web::Query<RevisionDiffQuery>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let comment = Comment::find(id.into_inner(), viewer)?;
+    Ok(HttpResponse::Ok().json(comment.diff(query.into_inner())?))
+}
+
+#[post("/comment/{id}/revisions/{revision}/restore")]
+async fn restore(
+    path: Path<(i32, i32)>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::CommentsWrite)?;
+    let (id, revision) = path.into_inner();
+    let comment = Comment::find_for_edit(id, &auth.user)?;
+    auth.user.authorize(Action::Edit, &comment)?;
+    Ok(HttpResponse::Ok().json(comment.restore(revision, &auth.user)?))
 }
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
@@ -70,4 +97,8 @@ pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(delete);
     cfg.service(create);
     cfg.service(edit);
+    cfg.service(find_revisions);
+    cfg.service(find_revision);
+    cfg.service(diff);
+    cfg.service(restore);
 }
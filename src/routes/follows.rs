@@ -0,0 +1,106 @@
+use crate::{db::Pagination, ApiError, AuthenticatedUser, Post, Scope, User};
+use actix_web::{
+    delete, get, put,
+    web::{self, Path, Query},
+    HttpResponse,
+};
+use validator::Validate;
+
+#[put("/user/{username}/follow")]
+async fn follow(username: Path<String>, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.follow(&User::by_name(username.into_inner())?)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/user/{username}/follow")]
+async fn unfollow(
+    username: Path<String>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.unfollow(&User::by_name(username.into_inner())?)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/user/{username}/followers")]
+async fn followers(
+    username: Path<String>, page: Query<Pagination>,
+) -> Result<HttpResponse, ApiError> {
+    page.validate()?;
+    let user = User::by_name(username.into_inner())?;
+    Ok(HttpResponse::Ok().json(user.followers(page.into_inner())?))
+}
+
+#[get("/user/{username}/following")]
+async fn following(
+    username: Path<String>, page: Query<Pagination>,
+) -> Result<HttpResponse, ApiError> {
+    page.validate()?;
+    let user = User::by_name(username.into_inner())?;
+    Ok(HttpResponse::Ok().json(user.following(page.into_inner())?))
+}
+
+#[put("/user/{username}/block")]
+async fn block(username: Path<String>, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.block(&User::by_name(username.into_inner())?)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/user/{username}/block")]
+async fn unblock(
+    username: Path<String>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.unblock(&User::by_name(username.into_inner())?)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/blocks")]
+async fn blocks(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    Ok(HttpResponse::Ok().json(auth.user.blocked_users()?))
+}
+
+#[put("/user/{username}/mute")]
+async fn mute(username: Path<String>, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.mute(&User::by_name(username.into_inner())?)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/user/{username}/mute")]
+async fn unmute(
+    username: Path<String>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.unmute(&User::by_name(username.into_inner())?)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[get("/mutes")]
+async fn mutes(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    Ok(HttpResponse::Ok().json(auth.user.muted_users()?))
+}
+
+#[get("/feed")]
+async fn feed(page: Query<Pagination>, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::Read)?;
+    page.validate()?;
+    Ok(HttpResponse::Ok().json(Post::tagged(auth.user.feed(page.into_inner())?)?))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(follow);
+    cfg.service(unfollow);
+    cfg.service(followers);
+    cfg.service(following);
+    cfg.service(block);
+    cfg.service(unblock);
+    cfg.service(blocks);
+    cfg.service(mute);
+    cfg.service(unmute);
+    cfg.service(mutes);
+    cfg.service(feed);
+}
@@ -0,0 +1,113 @@
+use crate::{config, storage::storage, Action, ApiError, AuthenticatedUser, Media, Scope};
+use actix_multipart::{Multipart, MultipartError};
+use actix_web::{
+    delete, get,
+    http::header::{self, CacheControl, CacheDirective},
+    post,
+    web::{self, Path},
+    HttpResponse,
+};
+use futures_util::TryStreamExt;
+use lazy_static::lazy_static;
+use uuid::Uuid;
+
+lazy_static! {
+    /// The largest file that can be uploaded, in bytes.
+    static ref MEDIA_MAX_BYTES: usize = config::var_or("MEDIA_MAX_BYTES", 10 * 1024 * 1024);
+}
+
+/// Reads the `file` field of a multipart upload, failing once it gets larger
+/// than `MEDIA_MAX_BYTES`.
+async fn read_file(mut payload: Multipart) -> Result<Vec<u8>, ApiError> {
+    let invalid = |e: MultipartError| ApiError::new(400, format!("Invalid upload: {}", e));
+
+    while let Some(mut field) = payload.try_next().await.map_err(invalid)? {
+        if field.name() != "file" {
+            continue;
+        }
+
+        let mut data = Vec::new();
+        while let Some(chunk) = field.try_next().await.map_err(invalid)? {
+            if data.len() + chunk.len() > *MEDIA_MAX_BYTES {
+                return Err(ApiError::new(
+                    413,
+                    format!("Files can be at most {} bytes large.", *MEDIA_MAX_BYTES),
+                ));
+            }
+            data.extend_from_slice(&chunk);
+        }
+        return Ok(data);
+    }
+
+    Err(ApiError::new(400, "Missing file.".into()))
+}
+
+/// Runs blocking work like image processing on the thread pool.
+async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, ApiError> + Send + 'static,
+) -> Result<T, ApiError> {
+    web::block(f)
+        .await
+        .map_err(|e| ApiError::new(500, format!("Blocking task failed: {}", e)))?
+}
+
+#[get("/media")]
+async fn find_all(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::Read)?;
+    Ok(HttpResponse::Ok().json(auth.user.media()?))
+}
+
+#[post("/media")]
+async fn upload(payload: Multipart, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::MediaWrite)?;
+    let data = read_file(payload).await?;
+    let media = blocking(move || Media::upload(&auth.user, &data)).await?;
+    Ok(HttpResponse::Created().json(media))
+}
+
+#[delete("/media/{id}")]
+async fn delete(id: Path<Uuid>, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::MediaWrite)?;
+    let media = Media::find(id.into_inner())?;
+    auth.user.authorize(Action::Delete, &media)?;
+    media.delete()?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/avatar")]
+async fn set_avatar(payload: Multipart, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let data = read_file(payload).await?;
+    let user = blocking(move || auth.user.set_avatar(&data)).await?;
+    Ok(HttpResponse::Ok().json(user.profile()?))
+}
+
+/// Serves files from the local storage. Their names are random and never
+/// reused, so they can be cached forever.
+#[get("/files/{key:.*}")]
+async fn file(key: Path<String>) -> Result<HttpResponse, ApiError> {
+    let key = key.into_inner();
+    let content_type = match key.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("jpg") => "image/jpeg",
+        Some("png") => "image/png",
+        _ => return Err(ApiError::new(404, "File not found.".into())),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(31_536_000),
+            CacheDirective::Extension("immutable".into(), None),
+        ]))
+        .insert_header((header::X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .body(storage().get(&key)?))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(find_all);
+    cfg.service(upload);
+    cfg.service(delete);
+    cfg.service(set_avatar);
+    cfg.service(file);
+}
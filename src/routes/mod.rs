@@ -3,9 +3,21 @@ use actix_web::web;
 mod posts;
 mod users;
 mod comments;
+mod two_factor;
+mod oidc;
+mod api_keys;
+mod media;
+mod follows;
+mod search;
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     users::init_routes(cfg);
     posts::init_routes(cfg);
     comments::init_routes(cfg);
+    two_factor::init_routes(cfg);
+    oidc::init_routes(cfg);
+    api_keys::init_routes(cfg);
+    media::init_routes(cfg);
+    follows::init_routes(cfg);
+    search::init_routes(cfg);
 }
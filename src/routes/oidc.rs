@@ -0,0 +1,145 @@
+use super::users::attempt_limiter;
+use crate::{
+    oidc::{Claims, Provider},
+    rate_limit::RateLimiter, ApiError, AuthenticatedUser, ClientInfo,
+    LoginChallenge, OidcCallback, OidcState, User,
+};
+use actix_web::{
+    cookie::{time::Duration, Cookie, SameSite},
+    get, post,
+    web::{self, Json, Path},
+    HttpRequest, HttpResponse,
+};
+use lazy_static::lazy_static;
+use serde_json::json;
+
+lazy_static! {
+    /// Authorization requests per client IP address, as each one is stored
+    /// until it expires.
+    static ref AUTHORIZATIONS_BY_IP: RateLimiter =
+        attempt_limiter("OIDC_AUTHORIZATIONS_PER_IP", 20);
+}
+
+/// The cookie binding an authorization request to the browser that started
+/// it, so a callback with someone else's `state` is rejected.
+const STATE_COOKIE: &str = "oidc_state";
+
+/// Builds the state cookie. It's `SameSite=Strict`, so other sites can neither
+/// set it nor send it along with their requests.
+fn state_cookie(state: &str) -> Cookie<'static> {
+    Cookie::build(STATE_COOKIE, state.to_owned())
+        .path("/oidc")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .max_age(Duration::minutes(OidcState::MINUTES))
+        .finish()
+}
+
+#[get("/oidc/{provider}/authorize")]
+async fn authorize(provider: Path<String>, client: ClientInfo) -> Result<HttpResponse, ApiError> {
+    let provider = Provider::find(&provider.into_inner())?;
+    if let Some(ip) = client.ip_address.as_deref() {
+        if let Some(wait) = AUTHORIZATIONS_BY_IP.retry_after(ip) {
+            return Err(ApiError::too_many_requests(wait));
+        }
+        AUTHORIZATIONS_BY_IP.record(ip);
+    }
+    let metadata = provider.discover().await?;
+    let request = provider.authorize(&metadata)?;
+    OidcState::save(&provider.name, &request)?;
+    Ok(HttpResponse::Ok()
+        .cookie(state_cookie(&request.state))
+        .json(json!({ "url": request.url })))
+}
+
+/// Completes the authorization request the callback belongs to, as long as
+/// this browser started it, and returns the claims of the user's identity.
+async fn identify(
+    req: &HttpRequest, provider: &Provider, OidcCallback { code, state }: OidcCallback,
+) -> Result<Claims, ApiError> {
+    if req.cookie(STATE_COOKIE).is_none_or(|cookie| cookie.value() != state) {
+        return Err(ApiError::new(
+            400, "The authorization request wasn't started by this browser.".into(),
+        ));
+    }
+    let state = OidcState::take(&provider.name, &state)?;
+    let metadata = provider.discover().await?;
+    provider
+        .exchange(&metadata, &code, &state.code_verifier, &state.nonce)
+        .await
+}
+
+/// Removes the state cookie once the authorization request is completed.
+fn removed_state_cookie() -> Cookie<'static> {
+    let mut cookie = state_cookie("");
+    cookie.make_removal();
+    cookie
+}
+
+#[post("/oidc/{provider}/callback")]
+async fn callback(
+    req: HttpRequest, provider: Path<String>, callback: Json<OidcCallback>, client: ClientInfo,
+) -> Result<HttpResponse, ApiError> {
+    let provider = Provider::find(&provider.into_inner())?;
+    let claims = identify(&req, &provider, callback.into_inner()).await?;
+    let user = User::from_identity(&provider.name, &claims)?;
+
+    if user.totp_enabled {
+        return Ok(HttpResponse::Accepted()
+            .cookie(removed_state_cookie())
+            .json(LoginChallenge::issue(&user)?));
+    }
+
+    info!("{:?} logged in through {}", user.username, provider.name);
+    Ok(HttpResponse::Ok()
+        .cookie(removed_state_cookie())
+        .json(user.get_token(client)?))
+}
+
+#[post("/oidc/{provider}/link")]
+async fn link(
+    req: HttpRequest, provider: Path<String>, request: Json<OidcCallback>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let provider = Provider::find(&provider.into_inner())?;
+    let claims = identify(&req, &provider, request.into_inner()).await?;
+    let identity = auth.user.link_identity(&provider.name, &claims)?;
+    Ok(HttpResponse::Created()
+        .cookie(removed_state_cookie())
+        .json(identity))
+}
+
+/// Confirms the user's identity for the current session by logging in again,
+/// e.g. to delete an account that has no password.
+#[post("/oidc/{provider}/reauthenticate")]
+async fn reauthenticate(
+    req: HttpRequest, provider: Path<String>, request: Json<OidcCallback>,
+    auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let session = auth.session()?;
+    let provider = Provider::find(&provider.into_inner())?;
+    let claims = identify(&req, &provider, request.into_inner()).await?;
+    if !auth.user.has_identity(&provider.name, &claims)? {
+        return Err(ApiError::new(403, "This identity isn't linked to your account.".into()));
+    }
+    session.reauthenticate()?;
+    Ok(HttpResponse::NoContent()
+        .cookie(removed_state_cookie())
+        .finish())
+}
+
+#[get("/identities")]
+async fn identities(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    Ok(HttpResponse::Ok().json(auth.user.identities()?))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(authorize);
+    cfg.service(callback);
+    cfg.service(link);
+    cfg.service(reauthenticate);
+    cfg.service(identities);
+}
@@ -1,80 +1,187 @@
-use crate::{ApiError, NewPost, Post, PostFilters, User, Session};
+use crate::{
+    db::Pagination, Action, ApiError, AuthenticatedUser, NewPost, Post, PostFilters,
+    RevisionDiffQuery, Scope, SlugTarget, TagMatch, User,
+};
 use actix_web::{
-    get, post, delete,
+    get, post, delete, put,
+    http::header,
     web::{self, Json, Path},
     HttpResponse,
 };
 use serde::Deserialize;
-use uuid::Uuid;
 use validator::Validate;
 
 #[get("/posts")]
-async fn find_all(filters: web::Query<PostFilters>) -> Result<HttpResponse, ApiError> {
+async fn find_all(
+    filters: web::Query<PostFilters>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
     filters.validate()?;
-    let posts = Post::find_all(filters.into_inner())?;
-    Ok(HttpResponse::Ok().json(posts))
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let posts = Post::find_all(filters.into_inner(), viewer)?;
+    Ok(HttpResponse::Ok().json(Post::tagged(posts)?))
 }
 
 #[get("/post/{id}")]
-async fn find(id: Path<i32>) -> Result<HttpResponse, ApiError> {
-    let post = Post::find(id.into_inner())?;
-    Ok(HttpResponse::Ok().json(post))
+async fn find(
+    id: Path<i32>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let post = Post::find(id.into_inner(), viewer)?;
+    Ok(HttpResponse::Ok().json(post.with_tags()?))
 }
 
-#[delete("/post/{id}")]
-async fn delete(
-    id: Path<i32>, session: Json<Session>,
+#[get("/user/{username}/post/{slug}")]
+async fn find_by_slug(
+    path: Path<(String, String)>, auth: Option<AuthenticatedUser>,
 ) -> Result<HttpResponse, ApiError> {
-    let Session { token } = session.into_inner();
-    let user = User::from_token(token)?;
-    let post = Post::find(id.into_inner())?;
-
-    if user.username != post.author {
-        Err(ApiError::new(403, "You can't delete this post.".into()))
-    } else {
-        post.delete()?;
-        Ok(HttpResponse::NoContent().finish())
+    let (username, slug) = path.into_inner();
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let target = match Post::by_slug(&username, &slug, viewer) {
+        Err(e) if e.status_code == 404 => {
+            // The author might have changed their username since.
+            let user = User::renamed_from(&username)?.ok_or(e)?;
+            return Ok(HttpResponse::TemporaryRedirect()
+                .insert_header((header::LOCATION, format!("/user/{}/post/{}", user.username, slug)))
+                .finish());
+        }
+        target => target?,
+    };
+
+    match target {
+        SlugTarget::Post(post) => Ok(HttpResponse::Ok().json(post.with_tags()?)),
+        SlugTarget::Moved(slug) => Ok(HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, format!("/user/{}/post/{}", username, slug)))
+            .finish()),
     }
 }
 
-#[derive(Deserialize)]
-struct CreateMessage {
-    pub post: NewPost,
-    pub token: Uuid,
+#[delete("/post/{id}")]
+async fn delete(
+    id: Path<i32>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::PostsWrite)?;
+    let post = Post::find_for_edit(id.into_inner(), &auth.user)?;
+    auth.user.authorize(Action::Delete, &post)?;
+    post.delete()?;
+    Ok(HttpResponse::NoContent().finish())
 }
 
 #[post("/post")]
-async fn create(data: Json<CreateMessage>) -> Result<HttpResponse, ApiError> {
-    let CreateMessage { post, token } = data.into_inner();
+async fn create(
+    post: Json<NewPost>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::PostsWrite)?;
+    let post = post.into_inner();
     post.validate()?;
-    let author = User::from_token(token)?;
-    let post = Post::try_from((post, &author))?;
-    Ok(HttpResponse::Created().json(post))
+    let post = Post::try_from((post, &auth.user))?;
+    Ok(HttpResponse::Created().json(post.with_tags()?))
+}
+
+#[put("/post/{id}")]
+async fn edit(
+    id: Path<i32>, post: Json<NewPost>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    edit_post(id.into_inner(), post.into_inner(), auth)
 }
 
 #[derive(Deserialize)]
 struct EditMessage {
     pub id: i32,
     pub post: NewPost,
-    pub token: Uuid,
 }
 
+/// The original way of editing posts, kept for existing clients.
 #[post("/edit")]
-async fn edit(data: Json<EditMessage>) -> Result<HttpResponse, ApiError> {
-    let EditMessage { id, post, token } = data.into_inner();
+async fn edit_legacy(
+    data: Json<EditMessage>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let EditMessage { id, post } = data.into_inner();
+    edit_post(id, post, auth)
+}
+
+fn edit_post(id: i32, post: NewPost, auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::PostsWrite)?;
     post.validate()?;
-    let old_post = Post::find(id)?;
-    let author = User::from_token(token)?;
-    if author.username != old_post.author {
-        return Err(ApiError::new(403, "You can't edit this post.".into()));
-    }
-    Ok(HttpResponse::Ok().json(old_post.edit(post)?))
+    let old_post = Post::find_for_edit(id, &auth.user)?;
+    auth.user.authorize(Action::Edit, &old_post)?;
+    Ok(HttpResponse::Ok().json(old_post.edit(post, &auth.user)?.with_tags()?))
+}
+
+#[get("/post/{id}/revisions")]
+async fn find_revisions(
+    id: Path<i32>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let post = Post::find(id.into_inner(), viewer)?;
+    Ok(HttpResponse::Ok().json(post.revisions()?))
+}
+
+#[get("/post/{id}/revisions/{revision}")]
+async fn find_revision(
+    path: Path<(i32, i32)>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, revision) = path.into_inner();
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let post = Post::find(id, viewer)?;
+    Ok(HttpResponse::Ok().json(post.revision(Some(revision))?))
+}
+
+#[get("/post/{id}/diff")]
+async fn diff(
+    id: Path<i32>, query: web::Query<RevisionDiffQuery>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let post = Post::find(id.into_inner(), viewer)?;
+    Ok(HttpResponse::Ok().json(post.diff(query.into_inner())?))
+}
+
+#[post("/post/{id}/revisions/{revision}/restore")]
+async fn restore(
+    path: Path<(i32, i32)>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.require(Scope::PostsWrite)?;
+    let (id, revision) = path.into_inner();
+    let post = Post::find_for_edit(id, &auth.user)?;
+    auth.user.authorize(Action::Edit, &post)?;
+    Ok(HttpResponse::Ok().json(post.restore(revision, &auth.user)?.with_tags()?))
+}
+
+#[get("/tags")]
+async fn tags(page: web::Query<Pagination>) -> Result<HttpResponse, ApiError> {
+    page.validate()?;
+    Ok(HttpResponse::Ok().json(Post::tag_counts(page.into_inner())?))
+}
+
+#[get("/tag/{tag}")]
+async fn tag(
+    tag: Path<String>, page: web::Query<Pagination>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    page.validate()?;
+    let page = page.into_inner();
+    let filters = PostFilters {
+        offset: page.offset,
+        limit: page.limit,
+        author: None,
+        tag: Some(tag.into_inner()),
+        tag_match: TagMatch::Any,
+    };
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    let posts = Post::find_all(filters, viewer)?;
+    Ok(HttpResponse::Ok().json(Post::tagged(posts)?))
 }
 
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(find_all);
     cfg.service(find);
+    cfg.service(find_by_slug);
     cfg.service(delete);
     cfg.service(create);
     cfg.service(edit);
+    cfg.service(edit_legacy);
+    cfg.service(find_revisions);
+    cfg.service(find_revision);
+    cfg.service(diff);
+    cfg.service(restore);
+    cfg.service(tags);
+    cfg.service(tag);
 }
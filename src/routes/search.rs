@@ -0,0 +1,20 @@
+use crate::{ApiError, AuthenticatedUser, SearchQuery, SearchResult};
+use actix_web::{
+    get,
+    web::{self, Query},
+    HttpResponse,
+};
+use validator::Validate;
+
+#[get("/search")]
+async fn search(
+    query: Query<SearchQuery>, auth: Option<AuthenticatedUser>,
+) -> Result<HttpResponse, ApiError> {
+    query.validate()?;
+    let viewer = auth.as_ref().and_then(AuthenticatedUser::viewer);
+    Ok(HttpResponse::Ok().json(SearchResult::search(query.into_inner(), viewer)?))
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(search);
+}
@@ -0,0 +1,59 @@
+use crate::{ApiError, AuthenticatedUser, TwoFactorCode};
+use actix_web::{
+    post,
+    web::{self, Json},
+    HttpResponse,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+#[post("/2fa/enroll")]
+async fn enroll(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    Ok(HttpResponse::Ok().json(auth.user.enroll_totp()?))
+}
+
+#[post("/2fa/confirm")]
+async fn confirm(
+    code: Json<TwoFactorCode>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let codes = auth.user.confirm_totp(&code.into_inner().code)?;
+    Ok(HttpResponse::Ok().json(json!({ "recoveryCodes": codes })))
+}
+
+#[post("/2fa/recovery-codes")]
+async fn regenerate_recovery_codes(
+    code: Json<TwoFactorCode>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    if !auth.user.totp_enabled {
+        return Err(ApiError::new(400, "Two-factor authentication isn't enabled.".into()));
+    }
+    if !auth.user.verify_second_factor(&code.into_inner().code)? {
+        return Err(ApiError::new(403, "Invalid code.".into()));
+    }
+    let codes = auth.user.regenerate_recovery_codes()?;
+    Ok(HttpResponse::Ok().json(json!({ "recoveryCodes": codes })))
+}
+
+#[derive(Deserialize)]
+struct DisableMessage {
+    password: String,
+}
+
+#[post("/2fa/disable")]
+async fn disable(
+    data: Json<DisableMessage>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.disable_totp(&data.into_inner().password)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn init_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(enroll);
+    cfg.service(confirm);
+    cfg.service(regenerate_recovery_codes);
+    cfg.service(disable);
+}
@@ -1,73 +1,372 @@
-use crate::{ApiError, Login, Registration, Token, User, Session, UserUpdate};
-use serde::Deserialize;
-use uuid::Uuid;
+use crate::{
+    config, rate_limit::RateLimiter, AccountDeletion, Action, ApiError, AuthenticatedUser,
+    ClientInfo, EmailVerification, Login, LoginChallenge, NewUsername, PasswordChange,
+    PasswordReset, PasswordResetConfirmation, PasswordResetRequest, Registration, Role, Token,
+    TwoFactorLogin, User, UserUpdate,
+};
 use actix_web::{
-    get, post,
+    delete, get,
+    http::header,
+    post, put,
     web::{self, Json, Path},
     HttpResponse,
 };
+use lazy_static::lazy_static;
+use serde::Deserialize;
 use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
 use validator::Validate;
 
+lazy_static! {
+    /// Failed login attempts per client IP address.
+    static ref LOGIN_ATTEMPTS_BY_IP: RateLimiter =
+        attempt_limiter("LOGIN_ATTEMPTS_PER_IP", 20);
+    /// Failed login attempts per username.
+    static ref LOGIN_ATTEMPTS_BY_USERNAME: RateLimiter =
+        attempt_limiter("LOGIN_ATTEMPTS_PER_USERNAME", 5);
+    /// Username lookups (availability checks and taken usernames during
+    /// registration) per client IP address.
+    static ref USERNAME_LOOKUPS_BY_IP: RateLimiter =
+        attempt_limiter("USERNAME_LOOKUPS_PER_IP", 10);
+    /// Password reset requests per client IP address.
+    static ref PASSWORD_RESETS_BY_IP: RateLimiter =
+        attempt_limiter("PASSWORD_RESETS_PER_IP", 10);
+    /// Password reset requests per username, so nobody gets flooded with mail.
+    static ref PASSWORD_RESETS_BY_USERNAME: RateLimiter =
+        attempt_limiter("PASSWORD_RESETS_PER_USERNAME", 3);
+}
+
+/// Creates a `RateLimiter` allowing the number of free attempts configured by
+/// `var` and locking out for at most `LOGIN_LOCKOUT_SECONDS`.
+pub(crate) fn attempt_limiter(var: &str, free_attempts: u32) -> RateLimiter {
+    RateLimiter::new(
+        config::var_or(var, free_attempts),
+        Duration::from_secs(1),
+        Duration::from_secs(config::var_or("LOGIN_LOCKOUT_SECONDS", 900)),
+    )
+}
+
 #[get("/user/{username}")]
 async fn find(username: Path<String>) -> Result<HttpResponse, ApiError> {
+    let username = username.into_inner();
+    match User::by_name(username.clone()) {
+        Ok(user) => Ok(HttpResponse::Ok().json(user.profile()?)),
+        Err(e) if e.status_code == 404 => {
+            // Not permanent, as the old name can be taken once it's no longer
+            // reserved.
+            let user = User::renamed_from(&username)?.ok_or(e)?;
+            Ok(HttpResponse::TemporaryRedirect()
+                .insert_header((header::LOCATION, format!("/user/{}", user.username)))
+                .finish())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct RoleUpdate {
+    role: Role,
+}
+
+#[put("/user/{username}/role")]
+async fn set_role(
+    username: Path<String>, role: Json<RoleUpdate>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
     let user = User::by_name(username.into_inner())?;
-    Ok(HttpResponse::Ok().json(user))
+    auth.user.authorize(Action::ChangeRole, &user)?;
+    Ok(HttpResponse::Ok().json(user.set_role(role.into_inner().role)?))
+}
+
+/// Fails if the client looked up too many usernames recently, so accounts can't
+/// be enumerated quickly. Clients without an address aren't limited, rather
+/// than all of them sharing one limit.
+fn check_username_lookups(ip: Option<&str>) -> Result<(), ApiError> {
+    match ip.and_then(|ip| USERNAME_LOOKUPS_BY_IP.retry_after(ip)) {
+        Some(wait) => Err(ApiError::too_many_requests(wait)),
+        None => Ok(()),
+    }
+}
+
+/// Counts a username lookup by the client towards its limit.
+fn record_username_lookup(ip: Option<&str>) {
+    if let Some(ip) = ip {
+        USERNAME_LOOKUPS_BY_IP.record(ip);
+    }
 }
 
 #[post("/user")]
-async fn register(form: Json<Registration>) -> Result<HttpResponse, ApiError> {
+async fn register(
+    form: Json<Registration>, client: ClientInfo,
+) -> Result<HttpResponse, ApiError> {
     let form = form.into_inner();
     form.validate()?;
-    let user = User::try_from(form)?;
+    let ip = client.ip_address.as_deref();
+    check_username_lookups(ip)?;
+    let user = User::try_from(form).inspect_err(|e| {
+        if e.status_code == 409 {
+            record_username_lookup(ip);
+        }
+    })?;
     Ok(HttpResponse::Created().json(user))
 }
 
+#[derive(Deserialize, Validate)]
+struct AvailabilityQuery {
+    #[validate(custom = "User::valid_username")]
+    username: String,
+}
+
+#[get("/username/available")]
+async fn username_available(
+    query: web::Query<AvailabilityQuery>, client: ClientInfo,
+) -> Result<HttpResponse, ApiError> {
+    let query = query.into_inner();
+    query.validate()?;
+    let ip = client.ip_address.as_deref();
+    check_username_lookups(ip)?;
+    record_username_lookup(ip);
+    Ok(HttpResponse::Ok().json(json!({
+        "available": !User::username_taken(&query.username)?
+    })))
+}
+
 #[post("/login")]
-async fn login(form: Json<Login>) -> Result<HttpResponse, ApiError> {
+async fn login(form: Json<Login>, client: ClientInfo) -> Result<HttpResponse, ApiError> {
     let form = form.into_inner();
-    let user = User::try_from(form)?;
-    let token = user.get_token()?;
+    let ip = client.ip_address.clone();
+    let username = form.username.to_ascii_lowercase();
+
+    // Without an address, e.g. behind a misconfigured proxy, only the
+    // username is limited rather than every client sharing one limit.
+    let wait = ip
+        .as_deref()
+        .and_then(|ip| LOGIN_ATTEMPTS_BY_IP.retry_after(ip))
+        .max(LOGIN_ATTEMPTS_BY_USERNAME.retry_after(&username));
+    if let Some(wait) = wait {
+        return Err(ApiError::too_many_requests(wait));
+    }
+
+    let user = User::try_from(form).inspect_err(|e| {
+        if e.status_code < 500 {
+            if let Some(ip) = &ip {
+                LOGIN_ATTEMPTS_BY_IP.record(ip);
+            }
+            LOGIN_ATTEMPTS_BY_USERNAME.record(&username);
+        }
+    })?;
+
+    if user.totp_enabled {
+        return Ok(HttpResponse::Accepted().json(LoginChallenge::issue(&user)?));
+    }
+
+    LOGIN_ATTEMPTS_BY_USERNAME.reset(&username);
+    let token = user.get_token(client)?;
     Ok(HttpResponse::Ok().json(token))
 }
 
-#[derive(Deserialize)]
-struct UpdateMessage {
-    update: UserUpdate,
-    token: Uuid,
+#[post("/login/2fa")]
+async fn login_two_factor(
+    form: Json<TwoFactorLogin>, client: ClientInfo,
+) -> Result<HttpResponse, ApiError> {
+    let form = form.into_inner();
+    let challenge = LoginChallenge::find(&form.challenge)?;
+    let username = User::find(challenge.user)?.username;
+
+    if let Some(wait) = LOGIN_ATTEMPTS_BY_USERNAME.retry_after(&username) {
+        return Err(ApiError::too_many_requests(wait));
+    }
+
+    let user = challenge.complete(&form.code).inspect_err(|e| {
+        if e.status_code < 500 {
+            LOGIN_ATTEMPTS_BY_USERNAME.record(&username);
+        }
+    })?;
+    LOGIN_ATTEMPTS_BY_USERNAME.reset(&username);
+
+    let token = user.get_token(client)?;
+    Ok(HttpResponse::Ok().json(token))
 }
 
 #[post("/preferences")]
-async fn update(data: Json<UpdateMessage>) -> Result<HttpResponse, ApiError> {
-    let UpdateMessage { update, token } = data.into_inner();
+async fn update(
+    update: Json<UserUpdate>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let update = update.into_inner();
     update.validate()?;
-    let user = User::from_token(token)?;
-    Ok(HttpResponse::Ok().json(user.update(update)?))
+    Ok(HttpResponse::Ok().json(auth.user.update(update)?))
 }
 
 #[get("/logout")]
-async fn logout(session: web::Query<Session>) -> Result<HttpResponse, ApiError> {
-    let token = Token::find(session.into_inner().token)?;
-    token.delete()?;
+async fn logout(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?.delete()?;
     Ok(HttpResponse::NoContent().finish())
 }
 
 #[get("/session")]
-async fn get_session(session: web::Query<Session>) -> Result<HttpResponse, ApiError> {
-    let Session { token } = session.into_inner();
-    let user = User::from_token(token)?;
-
+async fn get_session(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
     Ok(HttpResponse::Ok().json(json!({
-        "expires": Token::find(token)?.expiration,
-        "user": user
+        "expires": auth.session()?.expiration,
+        "twoFactorEnabled": auth.user.totp_enabled,
+        "email": auth.user.email,
+        "emailVerified": auth.user.email_verified,
+        "user": auth.user
     })))
 }
 
+#[post("/session/refresh")]
+async fn refresh_session(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    let token = auth.session()?.rotate()?;
+    Ok(HttpResponse::Ok().json(token))
+}
+
+#[get("/sessions")]
+async fn sessions(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    Ok(HttpResponse::Ok().json(auth.user.tokens()?))
+}
+
+#[delete("/sessions/{id}")]
+async fn revoke_session(
+    id: Path<Uuid>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let token = Token::by_id(id.into_inner())?;
+    if token.user != auth.user.id {
+        return Err(ApiError::new(404, "Record not found".into()));
+    }
+    token.delete()?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[delete("/sessions")]
+async fn revoke_other_sessions(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    let current = auth.session()?;
+    auth.user.revoke_tokens(Some(current.id))?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/password")]
+async fn change_password(
+    change: Json<PasswordChange>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let change = change.into_inner();
+    change.validate()?;
+    let session = auth.session()?;
+    auth.user.change_password(change, session.id)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/password/reset")]
+async fn request_password_reset(
+    request: Json<PasswordResetRequest>, client: ClientInfo,
+) -> Result<HttpResponse, ApiError> {
+    let username = request.into_inner().username;
+    let key = username.to_ascii_lowercase();
+    let ip = client.ip_address;
+
+    let wait = ip
+        .as_deref()
+        .and_then(|ip| PASSWORD_RESETS_BY_IP.retry_after(ip))
+        .max(PASSWORD_RESETS_BY_USERNAME.retry_after(&key));
+    if let Some(wait) = wait {
+        return Err(ApiError::too_many_requests(wait));
+    }
+    if let Some(ip) = &ip {
+        PASSWORD_RESETS_BY_IP.record(ip);
+    }
+    PASSWORD_RESETS_BY_USERNAME.record(&key);
+
+    // Always respond the same way and right away, so neither the response nor
+    // its timing tells which usernames exist.
+    actix_rt::task::spawn_blocking(move || {
+        if let Ok(user) = User::by_name(username) {
+            if let Err(e) = PasswordReset::send(&user) {
+                error!("Couldn't send password reset: {}", e);
+            }
+        }
+    });
+    Ok(HttpResponse::Accepted().finish())
+}
+
+#[post("/password/reset/confirm")]
+async fn confirm_password_reset(
+    confirmation: Json<PasswordResetConfirmation>,
+) -> Result<HttpResponse, ApiError> {
+    let confirmation = confirmation.into_inner();
+    confirmation.validate()?;
+    PasswordReset::confirm(confirmation)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/username")]
+async fn change_username(
+    change: Json<NewUsername>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let change = change.into_inner();
+    change.validate()?;
+    Ok(HttpResponse::Ok().json(auth.user.change_username(change)?))
+}
+
+#[get("/me/export")]
+async fn export(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    let export = auth.user.export()?;
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.json\"", auth.user.username),
+        ))
+        .json(export))
+}
+
+#[delete("/me")]
+async fn delete_account(
+    deletion: Json<AccountDeletion>, auth: AuthenticatedUser,
+) -> Result<HttpResponse, ApiError> {
+    let session = auth.session()?;
+    auth.user.delete(deletion.into_inner(), session)?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/email/verify")]
+async fn verify_email(
+    verification: Json<EmailVerification>,
+) -> Result<HttpResponse, ApiError> {
+    User::verify_email(verification.into_inner())?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[post("/email/resend")]
+async fn resend_email_verification(auth: AuthenticatedUser) -> Result<HttpResponse, ApiError> {
+    auth.session()?;
+    auth.user.send_email_verification()?;
+    Ok(HttpResponse::Accepted().finish())
+}
+
 pub fn init_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(find);
+    cfg.service(set_role);
     cfg.service(register);
+    cfg.service(username_available);
     cfg.service(login);
+    cfg.service(login_two_factor);
     cfg.service(update);
+    cfg.service(change_username);
     cfg.service(logout);
     cfg.service(get_session);
+    cfg.service(refresh_session);
+    cfg.service(sessions);
+    cfg.service(revoke_session);
+    cfg.service(revoke_other_sessions);
+    cfg.service(change_password);
+    cfg.service(request_password_reset);
+    cfg.service(confirm_password_reset);
+    cfg.service(export);
+    cfg.service(delete_account);
+    cfg.service(verify_email);
+    cfg.service(resend_email_verification);
 }
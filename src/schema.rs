@@ -1,5 +1,36 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (id) {
+        id -> Uuid,
+        user -> Uuid,
+        name -> Varchar,
+        hash -> Text,
+        scopes -> Array<Text>,
+        expiration -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    blocks (blocker, blocked) {
+        blocker -> Uuid,
+        blocked -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    comment_revisions (id) {
+        id -> Int4,
+        comment -> Int4,
+        editor -> Nullable<Uuid>,
+        message -> Text,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     comments (id) {
         id -> Int4,
@@ -8,6 +39,105 @@ diesel::table! {
         message -> Text,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        message_html -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    follows (follower, followee) {
+        follower -> Uuid,
+        followee -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    identities (id) {
+        id -> Int4,
+        user -> Uuid,
+        provider -> Text,
+        subject -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    login_challenges (id) {
+        id -> Uuid,
+        user -> Uuid,
+        hash -> Text,
+        expiration -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    media (id) {
+        id -> Uuid,
+        user -> Uuid,
+        content_type -> Text,
+        extension -> Text,
+        size -> Int4,
+        width -> Int4,
+        height -> Int4,
+        thumbnails -> Array<Int4>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    mutes (muter, muted) {
+        muter -> Uuid,
+        muted -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    oidc_states (hash) {
+        hash -> Text,
+        provider -> Text,
+        nonce -> Text,
+        code_verifier -> Text,
+        expiration -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    old_post_slugs (post, slug) {
+        post -> Int4,
+        slug -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    password_resets (id) {
+        id -> Uuid,
+        user -> Uuid,
+        hash -> Text,
+        expiration -> Timestamp,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    post_revisions (id) {
+        id -> Int4,
+        post -> Int4,
+        editor -> Nullable<Uuid>,
+        title -> Varchar,
+        subtitle -> Varchar,
+        body -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    post_tags (post, tag) {
+        post -> Int4,
+        tag -> Varchar,
     }
 }
 
@@ -20,6 +150,19 @@ diesel::table! {
         body -> Text,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        status -> Varchar,
+        published_at -> Nullable<Timestamp>,
+        slug -> Varchar,
+        body_html -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Int4,
+        user -> Uuid,
+        hash -> Text,
+        created_at -> Timestamp,
     }
 }
 
@@ -30,6 +173,21 @@ diesel::table! {
         expiration -> Timestamp,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        hash -> Text,
+        user_agent -> Nullable<Text>,
+        ip_address -> Nullable<Text>,
+        last_used_at -> Timestamp,
+        reauthenticated_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    username_changes (id) {
+        id -> Int4,
+        user -> Uuid,
+        old_username -> Varchar,
+        new_username -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -41,15 +199,55 @@ diesel::table! {
         about -> Nullable<Varchar>,
         created_at -> Timestamp,
         updated_at -> Nullable<Timestamp>,
+        role -> Varchar,
+        totp_secret -> Nullable<Text>,
+        totp_enabled -> Bool,
+        totp_last_step -> Nullable<Int8>,
+        email -> Nullable<Varchar>,
+        email_verified -> Bool,
+        display_name -> Nullable<Varchar>,
+        website -> Nullable<Varchar>,
+        links -> Array<Text>,
+        location -> Nullable<Varchar>,
+        pronouns -> Nullable<Varchar>,
+        avatar_url -> Nullable<Varchar>,
     }
 }
 
+diesel::joinable!(api_keys -> users (user));
+diesel::joinable!(comment_revisions -> comments (comment));
+diesel::joinable!(comment_revisions -> users (editor));
 diesel::joinable!(comments -> posts (post));
+diesel::joinable!(identities -> users (user));
+diesel::joinable!(login_challenges -> users (user));
+diesel::joinable!(media -> users (user));
+diesel::joinable!(old_post_slugs -> posts (post));
+diesel::joinable!(password_resets -> users (user));
+diesel::joinable!(post_revisions -> posts (post));
+diesel::joinable!(post_revisions -> users (editor));
+diesel::joinable!(post_tags -> posts (post));
+diesel::joinable!(recovery_codes -> users (user));
 diesel::joinable!(tokens -> users (user));
+diesel::joinable!(username_changes -> users (user));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    blocks,
+    comment_revisions,
     comments,
+    follows,
+    identities,
+    login_challenges,
+    media,
+    mutes,
+    oidc_states,
+    old_post_slugs,
+    password_resets,
+    post_revisions,
+    post_tags,
     posts,
+    recovery_codes,
     tokens,
+    username_changes,
     users,
 );
@@ -0,0 +1,110 @@
+//! Storage for uploaded files.
+
+use crate::{config, ApiError};
+use lazy_static::lazy_static;
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+};
+
+lazy_static! {
+    /// The `Storage` files are kept in, selected by the `STORAGE` environment
+    /// variable.
+    static ref STORAGE: Box<dyn Storage> = match config::var_or("STORAGE", "local".to_string())
+        .as_str()
+    {
+        "local" => Box::new(LocalStorage::new(
+            config::var_or("MEDIA_DIR", PathBuf::from("media")),
+            config::var_or("MEDIA_URL", "http://localhost:5000/files".to_string()),
+        )),
+        other => panic!("Unknown storage {:?}", other),
+    };
+}
+
+/// Somewhere files can be put and retrieved by a key like `a/b.png`.
+pub trait Storage: Send + Sync {
+    /// Stores `data` under `key`, replacing any existing file.
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), ApiError>;
+
+    /// Returns the file stored under `key`.
+    fn get(&self, key: &str) -> Result<Vec<u8>, ApiError>;
+
+    /// Removes the file stored under `key`, if any.
+    fn delete(&self, key: &str) -> Result<(), ApiError>;
+
+    /// Returns the public URL of the file stored under `key`.
+    fn url(&self, key: &str) -> String;
+}
+
+/// A `Storage` keeping files in a directory on the local filesystem. The files
+/// are served by the `/files` route.
+pub struct LocalStorage {
+    dir: PathBuf,
+    base_url: String,
+}
+
+impl LocalStorage {
+    pub fn new(dir: PathBuf, base_url: String) -> Self {
+        Self { dir, base_url }
+    }
+
+    /// Resolves `key` inside the directory, rejecting keys that would escape
+    /// it.
+    fn path(&self, key: &str) -> Result<PathBuf, ApiError> {
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(ApiError::new(404, "File not found.".into()));
+        }
+        Ok(self.dir.join(relative))
+    }
+}
+
+fn io_error(e: std::io::Error) -> ApiError {
+    if e.kind() == ErrorKind::NotFound {
+        ApiError::new(404, "File not found.".into())
+    } else {
+        ApiError::new(500, format!("Storage error: {}", e))
+    }
+}
+
+impl Storage for LocalStorage {
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), ApiError> {
+        let path = self.path(key)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+        fs::write(path, data).map_err(io_error)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>, ApiError> {
+        fs::read(self.path(key)?).map_err(io_error)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), ApiError> {
+        let path = self.path(key)?;
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(io_error(e)),
+            _ => {}
+        }
+
+        // Clean up directories left empty, which fails harmlessly otherwise.
+        let mut dir = path.parent();
+        while let Some(parent) = dir.filter(|dir| *dir != self.dir) {
+            if fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+        Ok(())
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), key)
+    }
+}
+
+/// Returns the configured `Storage`.
+pub fn storage() -> &'static dyn Storage {
+    STORAGE.as_ref()
+}
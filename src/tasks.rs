@@ -0,0 +1,63 @@
+//! Background tasks running alongside the server.
+
+use crate::{config, Comment, OidcState, Post};
+use lazy_static::lazy_static;
+use std::time::Duration;
+
+lazy_static! {
+    /// How often scheduled posts are checked for being due.
+    static ref PUBLISH_INTERVAL: Duration =
+        Duration::from_secs(config::var_or("PUBLISH_INTERVAL_SECONDS", 60));
+}
+
+/// Starts all background tasks on the current runtime.
+pub fn spawn() {
+    actix_rt::spawn(render_markdown());
+    actix_rt::spawn(publish_scheduled_posts());
+    actix_rt::spawn(delete_expired_oidc_states());
+}
+
+/// Renders the posts and comments whose HTML is missing, like the ones
+/// written before Markdown was rendered on write.
+async fn render_markdown() {
+    let rendered = actix_rt::task::spawn_blocking(|| {
+        Ok::<_, crate::ApiError>((Post::render_missing()?, Comment::render_missing()?))
+    })
+    .await;
+    match rendered {
+        Ok(Ok((0, 0))) => {}
+        Ok(Ok((posts, comments))) => {
+            info!("Rendered Markdown of {} posts and {} comments", posts, comments)
+        }
+        Ok(Err(e)) => error!("Couldn't render Markdown: {}", e),
+        Err(e) => error!("Rendering Markdown panicked: {}", e),
+    }
+}
+
+/// Periodically publishes scheduled posts that are due.
+async fn publish_scheduled_posts() {
+    let mut interval = actix_rt::time::interval(*PUBLISH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match actix_rt::task::spawn_blocking(Post::publish_scheduled).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => error!("Couldn't publish scheduled posts: {}", e),
+            Err(e) => error!("Publishing scheduled posts panicked: {}", e),
+        }
+    }
+}
+
+/// Periodically deletes the OIDC authorization requests that expired.
+async fn delete_expired_oidc_states() {
+    let mut interval =
+        actix_rt::time::interval(Duration::from_secs(OidcState::MINUTES as u64 * 60));
+    loop {
+        interval.tick().await;
+        match actix_rt::task::spawn_blocking(OidcState::delete_expired).await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(deleted)) => info!("Deleted {} expired OIDC authorization requests", deleted),
+            Ok(Err(e)) => error!("Couldn't delete expired OIDC authorization requests: {}", e),
+            Err(e) => error!("Deleting expired OIDC authorization requests panicked: {}", e),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Time-based one-time passwords as specified in RFC 6238, using HMAC-SHA1,
+//! 30 second steps and 6 digits, which is what authenticator apps expect.
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+
+/// The length of a time step in seconds.
+pub const STEP: u64 = 30;
+/// The number of digits of a code.
+pub const DIGITS: u32 = 6;
+/// The number of steps before and after the current one whose codes are still
+/// accepted, to allow for clock drift.
+pub const SKEW: u64 = 1;
+
+/// Generates a new random secret.
+pub fn generate_secret() -> Vec<u8> {
+    rand::thread_rng().gen::<[u8; 20]>().to_vec()
+}
+
+/// Encodes a secret as base32, the way it's shown to users.
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// Decodes a base32 encoded secret.
+pub fn decode_secret(secret: &str) -> Option<Vec<u8>> {
+    BASE32_NOPAD.decode(secret.as_bytes()).ok()
+}
+
+/// Returns the `otpauth://` URI used to enroll the secret in an authenticator
+/// app, usually shown as a QR code.
+///
+/// ```
+/// use ephemeris::totp;
+///
+/// assert_eq!(
+///     totp::uri(b"12345678901234567890", "ephemeris", "alice"),
+///     "otpauth://totp/ephemeris:alice?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+///     &issuer=ephemeris&algorithm=SHA1&digits=6&period=30",
+/// );
+/// ```
+pub fn uri(secret: &[u8], issuer: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={}&issuer={issuer}&algorithm=SHA1\
+        &digits={DIGITS}&period={STEP}",
+        encode_secret(secret),
+        issuer = percent_encode(issuer),
+        account = percent_encode(account),
+    )
+}
+
+/// Computes the code for the given time step.
+///
+/// ```
+/// use ephemeris::totp;
+///
+/// // Test vectors from RFC 6238.
+/// let secret = b"12345678901234567890";
+/// assert_eq!(totp::code(secret, 59 / totp::STEP), 287082);
+/// assert_eq!(totp::code(secret, 1111111109 / totp::STEP), 81804);
+/// assert_eq!(totp::code(secret, 2000000000 / totp::STEP), 279037);
+/// ```
+pub fn code(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Checks `code` against the codes of the steps around the Unix time `now`,
+/// skipping steps up to and including `last_step` so codes can't be reused.
+///
+/// Returns the step of the matching code.
+///
+/// ```
+/// use ephemeris::totp;
+///
+/// let secret = b"12345678901234567890";
+/// assert_eq!(totp::verify(secret, "287082", 59, None), Some(1));
+/// assert_eq!(totp::verify(secret, "287 082", 75, None), Some(1));
+/// assert_eq!(totp::verify(secret, "287082", 59, Some(1)), None);
+/// assert_eq!(totp::verify(secret, "287083", 59, None), None);
+/// ```
+pub fn verify(secret: &[u8], code: &str, now: u64, last_step: Option<u64>) -> Option<u64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let current = now / STEP;
+    (current.saturating_sub(SKEW)..=current + SKEW)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| self::code(secret, *step) == code)
+}
+
+/// Percent-encodes everything but unreserved characters.
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,61 @@
+use actix_web::{http::StatusCode, test, App};
+use diesel::prelude::*;
+use ephemeris::{
+    db, routes::init_routes, schema::users, ClientInfo, NewPost, Post, Registration, Role, User,
+};
+
+/// Registers a user with a random name, as the database is shared between
+/// test runs.
+fn register(prefix: &str) -> User {
+    let suffix = uuid::Uuid::new_v4().to_simple().to_string();
+    User::try_from(Registration {
+        username: format!("{}{}", prefix, &suffix[..8]),
+        password: "password".into(),
+        email: None,
+    })
+    .unwrap()
+}
+
+#[actix_rt::test]
+#[ignore = "needs a migrated database at DATABASE_URL"]
+async fn moderator_deletes_post_of_author_who_blocked_them() {
+    dotenvy::dotenv().ok();
+    let author = register("author");
+    let moderator = register("moderator").set_role(Role::Moderator).unwrap();
+    author.block(&moderator).unwrap();
+    let post = Post::try_from((
+        NewPost {
+            title: "Blocked".into(),
+            subtitle: String::new(),
+            body: "Against the rules.".into(),
+            status: None,
+            publish_at: None,
+            tags: None,
+        },
+        &author,
+    ))
+    .unwrap();
+    let token = moderator
+        .get_token(ClientInfo { user_agent: None, ip_address: None })
+        .unwrap()
+        .token;
+
+    let app = test::init_service(App::new().configure(init_routes)).await;
+    let request = |method: test::TestRequest| {
+        method
+            .uri(&format!("/post/{}", post.id))
+            .insert_header(("Authorization", format!("Bearer {}", token)))
+            .to_request()
+    };
+
+    // Reading still hides the post, but deleting it is up to the role.
+    let response = test::call_service(&app, request(test::TestRequest::get())).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = test::call_service(&app, request(test::TestRequest::delete())).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(Post::find(post.id, None).is_err());
+
+    diesel::delete(users::table.filter(users::id.eq_any([author.id, moderator.id])))
+        .execute(&mut db::connection().unwrap())
+        .unwrap();
+}
@@ -0,0 +1,170 @@
+use actix_web::{get, post, web, App, HttpResponse, HttpServer};
+use data_encoding::BASE64URL_NOPAD;
+use ephemeris::oidc::{code_challenge, Claims, Provider};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+
+const CLIENT_ID: &str = "ephemeris";
+const CODE: &str = "authorization-code";
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const CODE_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+const NONCE: &str = "nonce";
+
+/// The URL the mock provider is reachable at.
+struct Issuer(String);
+
+#[get("/.well-known/openid-configuration")]
+async fn discovery(issuer: web::Data<Issuer>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": issuer.0,
+        "authorization_endpoint": format!("{}/authorize", issuer.0),
+        "token_endpoint": format!("{}/token", issuer.0),
+    }))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    client_id: String,
+    code_verifier: String,
+}
+
+#[post("/token")]
+async fn token(issuer: web::Data<Issuer>, form: web::Form<TokenRequest>) -> HttpResponse {
+    if form.grant_type != "authorization_code"
+        || form.code != CODE
+        || form.client_id != CLIENT_ID
+        || code_challenge(&form.code_verifier) != CODE_CHALLENGE
+    {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    }
+
+    HttpResponse::Ok().json(json!({
+        "access_token": "access-token",
+        "token_type": "Bearer",
+        "id_token": id_token(json!({
+            "iss": issuer.0,
+            "sub": "1234",
+            "aud": [CLIENT_ID],
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "nonce": NONCE,
+            "preferred_username": "Jane Doe",
+            "email": "jane@example.com",
+            "email_verified": true,
+        })),
+    }))
+}
+
+/// Builds an unsigned ID token with the given claims.
+fn id_token(claims: serde_json::Value) -> String {
+    format!(
+        "{}.{}.",
+        BASE64URL_NOPAD.encode(br#"{"alg":"none"}"#),
+        BASE64URL_NOPAD.encode(claims.to_string().as_bytes()),
+    )
+}
+
+/// Starts the mock provider and returns a `Provider` pointing to it.
+fn start_provider() -> Provider {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+    let data = web::Data::new(Issuer(issuer.clone()));
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .service(discovery)
+            .service(token)
+    })
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+
+    Provider {
+        name: "mock".into(),
+        issuer,
+        client_id: CLIENT_ID.into(),
+        client_secret: None,
+        redirect_url: "http://localhost:3000/callback".into(),
+    }
+}
+
+#[actix_rt::test]
+async fn authorization_code_flow() {
+    let provider = start_provider();
+    let metadata = provider.discover().await.unwrap();
+
+    let request = provider.authorize(&metadata).unwrap();
+    let url = url::Url::parse(&request.url).unwrap();
+    let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+    assert_eq!(url.path(), "/authorize");
+    assert_eq!(query["client_id"], CLIENT_ID);
+    assert_eq!(query["state"], request.state);
+    assert_eq!(query["nonce"], request.nonce);
+    assert_eq!(query["code_challenge"], code_challenge(&request.code_verifier));
+    assert_eq!(query["code_challenge_method"], "S256");
+
+    let claims = provider
+        .exchange(&metadata, CODE, CODE_VERIFIER, NONCE)
+        .await
+        .unwrap();
+    assert_eq!(
+        claims,
+        Claims {
+            subject: "1234".into(),
+            preferred_username: Some("Jane Doe".into()),
+            email: Some("jane@example.com".into()),
+            email_verified: true,
+            name: None,
+        }
+    );
+}
+
+#[actix_rt::test]
+async fn rejects_wrong_code_verifier() {
+    let provider = start_provider();
+    let metadata = provider.discover().await.unwrap();
+
+    let error = provider
+        .exchange(&metadata, CODE, "wrong-verifier", NONCE)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status_code, 401);
+}
+
+#[actix_rt::test]
+async fn rejects_wrong_nonce() {
+    let provider = start_provider();
+    let metadata = provider.discover().await.unwrap();
+
+    let error = provider
+        .exchange(&metadata, CODE, CODE_VERIFIER, "other nonce")
+        .await
+        .unwrap_err();
+    assert_eq!(error.status_code, 401);
+}
+
+#[actix_rt::test]
+async fn validates_id_token_claims() {
+    let provider = start_provider();
+    let metadata = provider.discover().await.unwrap();
+    let now = chrono::Utc::now().timestamp();
+    let claims = |iss: &str, aud: &str, exp: i64| {
+        id_token(json!({ "iss": iss, "sub": "1234", "aud": aud, "exp": exp, "nonce": NONCE }))
+    };
+
+    let valid = claims(&metadata.issuer, CLIENT_ID, now + 60);
+    assert!(provider.validate_id_token(&metadata, &valid, NONCE, now).is_ok());
+
+    for id_token in [
+        claims("https://evil.example", CLIENT_ID, now + 60),
+        claims(&metadata.issuer, "someone-else", now + 60),
+        claims(&metadata.issuer, CLIENT_ID, now - 1),
+        "not a token".to_string(),
+    ] {
+        assert!(provider.validate_id_token(&metadata, &id_token, NONCE, now).is_err());
+    }
+}